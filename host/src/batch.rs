@@ -0,0 +1,320 @@
+// Prove a directory of emails, several at a time.
+//
+// Every `.eml` in the directory is proven with the same params and written
+// to `<out>/<name>.receipt`. `<out>/manifest.json` records how each email
+// went and is rewritten after every one, so that an interrupted batch
+// resumes where it stopped: emails that are done, unchanged, and whose
+// receipt is still there are skipped. Failed emails are skipped too unless
+// `retry_failed` is set, as most failures are in the email itself.
+//
+// The manifest holds the salts of the committed fields, which are needed
+// to open the commitments, so it is as private as the emails.
+
+use crate::receipt::{Format, Guest, ReceiptFile};
+use crate::{hex, witness, Failure};
+use fps_types::ProofParams;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// The segments of risc0 are of 2^20 cycles unless told otherwise.
+const DEFAULT_SEGMENT_PO2: u32 = 20;
+
+/// Smaller segments save little memory and add a lot of proving time.
+const MIN_SEGMENT_PO2: u32 = 16;
+
+/// Rough peak memory of the prover per cycle of a segment: about 8 GiB
+/// for the default segments.
+const BYTES_PER_CYCLE: u64 = 8 << 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    status: Status,
+    /// SHA-256 of the email, so that a changed email is proven again.
+    sha256: String,
+    /// The file name of the receipt, in the output directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    receipt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nullifier: Option<String>,
+    /// The salts that open the committed fields, by field.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    salts: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Seconds spent on the email.
+    seconds: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    params: ProofParams,
+    /// By the file name of the email.
+    emails: BTreeMap<String, Entry>,
+}
+
+pub struct Options {
+    pub workers: usize,
+    /// In GiB; the segments are made small enough for all the workers to
+    /// fit.
+    pub memory_limit: Option<u64>,
+    pub params: ProofParams,
+    pub retry_failed: bool,
+}
+
+/// The largest segments with which `workers` proofs fit in `limit` GiB.
+fn segment_po2(workers: usize, limit: u64) -> Result<u32, Failure> {
+    let limit = limit << 30;
+    (MIN_SEGMENT_PO2..=DEFAULT_SEGMENT_PO2)
+        .rev()
+        .find(|po2| workers as u64 * (BYTES_PER_CYCLE << po2) <= limit)
+        .ok_or_else(|| {
+            Failure::input(format!(
+                "{} workers need about {} GiB at the least",
+                workers,
+                (workers as u64 * (BYTES_PER_CYCLE << MIN_SEGMENT_PO2) + (1 << 30) - 1) >> 30
+            ))
+        })
+}
+
+/// Write `data` next to `path` and rename it over, so that an interrupted
+/// batch never leaves a truncated file behind.
+fn replace(path: &Path, data: &[u8]) -> Result<(), Failure> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    crate::write(Path::new(&tmp), data)?;
+    fs::rename(&tmp, path).map_err(|e| Failure::input(format!("{}: {}", path.display(), e)))
+}
+
+/// The emails in `dir`, by file name.
+fn emails(dir: &Path) -> Result<Vec<String>, Failure> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| Failure::input(format!("{}: {}", dir.display(), e)))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| Failure::input(format!("{}: {}", dir.display(), e)))?;
+        let path = entry.path();
+        let eml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("eml"));
+        if eml && path.is_file() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+struct Batch<'a> {
+    dir: &'a Path,
+    out: &'a Path,
+    params: &'a ProofParams,
+    segment_po2: Option<u32>,
+    manifest: Mutex<Manifest>,
+}
+
+impl Batch<'_> {
+    fn save_manifest(&self, manifest: &Manifest) -> Result<(), Failure> {
+        let data = serde_json::to_vec_pretty(manifest).unwrap();
+        replace(&self.out.join("manifest.json"), &data)
+    }
+
+    /// Extract, prove and save one email.
+    fn prove(&self, name: &str, raw: &[u8]) -> Result<Entry, Failure> {
+        let extracted = witness::from_eml(raw).map_err(Failure::input)?;
+        let secrets = crate::random_secrets();
+        crate::check_inputs(&extracted.witness, &secrets)?;
+        let proven = panic::catch_unwind(AssertUnwindSafe(|| {
+            crate::prove_journal(&extracted.witness, self.params, &secrets, self.segment_po2)
+        }))
+        .unwrap_or_else(|_| Err(Failure::rejected("the prover panicked")))?;
+        let (receipt, output) = proven;
+
+        let file_name = format!("{}.receipt", name);
+        let file = ReceiptFile::new(Guest::Method, receipt);
+        replace(&self.out.join(&file_name), &file.to_bytes(Format::Binary))?;
+        Ok(Entry {
+            status: Status::Done,
+            sha256: String::new(),
+            receipt: Some(file_name),
+            nullifier: Some(hex(&output.nullifier)),
+            salts: crate::salts(self.params, &secrets)
+                .into_iter()
+                .map(|(field, salt)| (format!("{:?}", field), hex(&salt)))
+                .collect(),
+            error: None,
+            seconds: 0.0,
+        })
+    }
+
+    fn run(&self, name: &str) -> Result<Status, Failure> {
+        let timer = Instant::now();
+        let raw = crate::read(&self.dir.join(name))?;
+        let mut entry = self.prove(name, &raw).unwrap_or_else(|failure| Entry {
+            status: Status::Failed,
+            sha256: String::new(),
+            receipt: None,
+            nullifier: None,
+            salts: BTreeMap::new(),
+            error: Some(failure.message),
+            seconds: 0.0,
+        });
+        entry.sha256 = hex(&Sha256::digest(raw));
+        entry.seconds = timer.elapsed().as_secs_f64();
+        match &entry.error {
+            Some(error) => eprintln!("{}: failed: {}", name, error),
+            None => eprintln!("{}: done in {:.1} s", name, entry.seconds),
+        }
+
+        let status = entry.status;
+        if status == Status::Failed {
+            // A receipt of an earlier version of the email.
+            let _ = fs::remove_file(self.out.join(format!("{}.receipt", name)));
+        }
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.emails.insert(name.to_string(), entry);
+        self.save_manifest(&manifest)?;
+        Ok(status)
+    }
+
+    /// Whether `name` was already proven, or failed, with its current
+    /// content.
+    fn settled(&self, name: &str, retry_failed: bool) -> Result<bool, Failure> {
+        let manifest = self.manifest.lock().unwrap();
+        let Some(entry) = manifest.emails.get(name) else {
+            return Ok(false);
+        };
+        let settled = match (entry.status, &entry.receipt) {
+            (Status::Done, Some(receipt)) => self.out.join(receipt).is_file(),
+            (Status::Failed, _) => !retry_failed,
+            _ => false,
+        };
+        if !settled {
+            return Ok(false);
+        }
+        let raw = crate::read(&self.dir.join(name))?;
+        Ok(entry.sha256 == hex(&Sha256::digest(raw)))
+    }
+}
+
+pub fn batch(json: bool, dir: &Path, out: &Path, options: Options) -> Result<(), Failure> {
+    let workers = options.workers.max(1);
+    let segment_po2 = match options.memory_limit {
+        Some(limit) => Some(segment_po2(workers, limit)?),
+        None => None,
+    };
+    fs::create_dir_all(out).map_err(|e| Failure::input(format!("{}: {}", out.display(), e)))?;
+
+    let manifest_path = out.join("manifest.json");
+    let manifest = if manifest_path.exists() {
+        let manifest: Manifest = crate::read_json(&manifest_path)?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(Failure::input(format!(
+                "{}: manifest version {}, expected {}",
+                manifest_path.display(),
+                manifest.version,
+                MANIFEST_VERSION
+            )));
+        }
+        if manifest.params != options.params {
+            return Err(Failure::input(format!(
+                "{}: the batch was started with other params",
+                manifest_path.display()
+            )));
+        }
+        manifest
+    } else {
+        Manifest {
+            version: MANIFEST_VERSION,
+            params: options.params.clone(),
+            emails: BTreeMap::new(),
+        }
+    };
+
+    let batch = Batch {
+        dir,
+        out,
+        params: &options.params,
+        segment_po2,
+        manifest: Mutex::new(manifest),
+    };
+    batch.save_manifest(&batch.manifest.lock().unwrap())?;
+
+    let names = emails(dir)?;
+    let mut queue = VecDeque::new();
+    for name in &names {
+        if !batch.settled(name, options.retry_failed)? {
+            queue.push_back(name.as_str());
+        }
+    }
+    let skipped = names.len() - queue.len();
+    eprintln!(
+        "{} emails, {} to prove, {} workers",
+        names.len(),
+        queue.len(),
+        workers
+    );
+
+    let queue = Mutex::new(queue);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let Some(name) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let result = batch.run(name);
+                results.lock().unwrap().push(result);
+            });
+        }
+    });
+
+    let mut done = 0;
+    let mut failed = 0;
+    for result in results.into_inner().unwrap() {
+        match result? {
+            Status::Done => done += 1,
+            Status::Failed => failed += 1,
+        }
+    }
+    if json {
+        println!(
+            "{}",
+            json!({
+                "done": done,
+                "failed": failed,
+                "skipped": skipped,
+                "manifest": manifest_path.display().to_string(),
+            })
+        );
+    } else {
+        println!("done:     {}", done);
+        println!("failed:   {}", failed);
+        println!("skipped:  {}", skipped);
+        println!("manifest: {}", manifest_path.display());
+    }
+    if failed > 0 {
+        return Err(Failure::rejected(format!(
+            "{} of the emails failed, see {}",
+            failed,
+            manifest_path.display()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,478 @@
+// Matching a canonicalised email against the known templates, and a
+// byte-level report of where the closest template stops matching.
+
+use crate::eml::Canonical;
+use fps_types::template::{self, Field, Piece, Template};
+use std::fmt::Write;
+
+/// A piece of a template whose literal may be borrowed from a rendering.
+#[derive(Clone, Copy)]
+enum Seg<'a> {
+    Lit(&'a [u8]),
+    Field(Field),
+}
+
+fn segs(pieces: &'static [Piece]) -> Vec<Seg<'static>> {
+    pieces
+        .iter()
+        .map(|p| match p {
+            Piece::Lit(lit) => Seg::Lit(lit),
+            Piece::Field(field) => Seg::Field(*field),
+        })
+        .collect()
+}
+
+/// Where and why a match failed.
+pub struct Mismatch {
+    /// Offset into the input.
+    pub at: usize,
+    pub expected: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct Bindings {
+    fields: Vec<(Field, Vec<u8>)>,
+    parts: Vec<Vec<u8>>,
+}
+
+impl Bindings {
+    pub fn get(&self, field: Field) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Field, &[u8])> {
+        self.fields.iter().map(|(f, v)| (*f, v.as_slice()))
+    }
+
+    fn bind(&mut self, field: Field, value: &[u8], at: usize) -> Result<(), Mismatch> {
+        match self.get(field) {
+            Some(bound) if bound != value => Err(Mismatch {
+                at,
+                expected: bound.to_vec(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                self.fields.push((field, value.to_vec()));
+                Ok(())
+            }
+        }
+    }
+}
+
+impl template::Fields for Bindings {
+    fn field(&self, field: Field) -> &[u8] {
+        self.get(field).unwrap_or(b"")
+    }
+
+    fn extra_parts(&self) -> &[Vec<u8>] {
+        &self.parts
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Match `segs` against `input` starting at `pos`, returning the end.
+///
+/// A field extends to the first occurrence of the literal that follows it,
+/// or to the end of the input if it is the last segment.
+fn match_segs(
+    segs: &[Seg],
+    input: &[u8],
+    mut pos: usize,
+    bindings: &mut Bindings,
+) -> Result<usize, Mismatch> {
+    for (i, seg) in segs.iter().enumerate() {
+        match seg {
+            Seg::Lit(lit) => {
+                if !input[pos..].starts_with(lit) {
+                    let common = input[pos..]
+                        .iter()
+                        .zip(lit.iter())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    return Err(Mismatch {
+                        at: pos + common,
+                        expected: lit[common..].to_vec(),
+                    });
+                }
+                pos += lit.len();
+            }
+            Seg::Field(field) => {
+                let end = match segs.get(i + 1) {
+                    Some(Seg::Lit(next)) => match find(&input[pos..], next) {
+                        Some(len) => pos + len,
+                        None => {
+                            return Err(Mismatch {
+                                at: pos,
+                                expected: next.to_vec(),
+                            })
+                        }
+                    },
+                    _ => input.len(),
+                };
+                bindings.bind(*field, &input[pos..end], pos)?;
+                pos = end;
+            }
+        }
+    }
+    Ok(pos)
+}
+
+fn match_all(segs: &[Seg], input: &[u8], bindings: &mut Bindings) -> Result<(), Mismatch> {
+    let end = match_segs(segs, input, 0, bindings)?;
+    if end != input.len() {
+        return Err(Mismatch {
+            at: end,
+            expected: Vec::new(),
+        });
+    }
+    Ok(())
+}
+
+/// Remove quoted-printable soft line breaks.
+fn unwrap_soft_breaks(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i..].starts_with(b"=\r\n") {
+            i += 3;
+        } else {
+            out.push(data[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Try to read the witness fields out of `canonical` using `template`.
+pub fn extract(template: &Template, canonical: &Canonical) -> Result<Bindings, Mismatch> {
+    let mut bindings = Bindings::default();
+    let body = &canonical.body;
+
+    let mut head = vec![Seg::Lit(b"--")];
+    head.extend(segs(template.boundary));
+    head.push(Seg::Lit(b"\r\n"));
+    head.extend(segs(template.body_head));
+
+    // The paragraph is wrapped at a width that depends on the field lengths,
+    // so it is located by the literal that follows it and matched unwrapped.
+    let para_start = match_segs(&head, body, 0, &mut bindings)?;
+    let tail = segs(template.body_tail);
+    let para_end = match tail.first() {
+        Some(Seg::Lit(lit)) => find(&body[para_start..], lit).map(|p| para_start + p),
+        _ => None,
+    }
+    .ok_or(Mismatch {
+        at: para_start,
+        expected: Vec::new(),
+    })?;
+    let paragraph = unwrap_soft_breaks(&body[para_start..para_end]);
+    match_all(&segs(template.paragraph), &paragraph, &mut bindings)?;
+    let mut pos = match_segs(&tail, body, para_end, &mut bindings)?;
+
+    // Whatever follows the text part is split on the delimiter lines, and
+    // the parts in between are kept as opaque bytes.
+    let mut marker = b"\r\n".to_vec();
+    marker.extend_from_slice(&template.delimiter(&bindings));
+    loop {
+        if !body[pos..].starts_with(&marker) {
+            return Err(Mismatch {
+                at: pos,
+                expected: marker,
+            });
+        }
+        pos += marker.len();
+        if &body[pos..] == b"--\r\n" {
+            break;
+        }
+        if !body[pos..].starts_with(b"\r\n") {
+            return Err(Mismatch {
+                at: pos,
+                expected: b"\r\n".to_vec(),
+            });
+        }
+        pos += 2;
+        let len = find(&body[pos..], &marker).ok_or(Mismatch {
+            at: pos,
+            expected: marker.clone(),
+        })?;
+        bindings.parts.push(body[pos..pos + len].to_vec());
+        pos += len;
+    }
+
+    match_all(&segs(template.header), &canonical.header, &mut bindings)?;
+    match_all(
+        &segs(template.dkim_header),
+        &canonical.dkim.canonical,
+        &mut bindings,
+    )?;
+
+    // The wrapping must also be exactly what the template produces.
+    if !template.check_parts(&bindings)
+        || template.render_body(&bindings) != canonical.body
+        || !template.check_delimiters(&canonical.body, &bindings)
+        || template.render_header(&bindings) != canonical.header
+        || template.render_dkim_header(&bindings) != canonical.dkim.canonical
+    {
+        return Err(Mismatch {
+            at: 0,
+            expected: Vec::new(),
+        });
+    }
+
+    Ok(bindings)
+}
+
+/// The first template that the email fits, with the extracted fields.
+pub fn detect(canonical: &Canonical) -> Option<(&'static Template, Bindings)> {
+    template::TEMPLATES
+        .iter()
+        .find_map(|t| extract(t, canonical).ok().map(|b| (*t, b)))
+}
+
+/// Rendering of a template with fields left as slots, split into lines.
+///
+/// Soft line breaks are removed on both sides before diffing, so that a
+/// changed field length does not show up as a rewrapped paragraph.
+fn pattern_lines(sections: &[&'static [Piece]], soft_breaks: bool) -> Vec<Vec<Seg<'static>>> {
+    let mut lines: Vec<Vec<Seg<'static>>> = vec![Vec::new()];
+    for pieces in sections {
+        for piece in pieces.iter() {
+            match piece {
+                Piece::Field(field) => lines.last_mut().unwrap().push(Seg::Field(*field)),
+                Piece::Lit(lit) => {
+                    // Empty literals would make a preceding field match nothing.
+                    let push = |lines: &mut Vec<Vec<Seg<'static>>>, lit: &'static [u8]| {
+                        if !lit.is_empty() {
+                            lines.last_mut().unwrap().push(Seg::Lit(lit));
+                        }
+                    };
+                    let mut rest: &'static [u8] = lit;
+                    while let Some(p) = find(rest, b"\r\n") {
+                        if soft_breaks && rest[..p].ends_with(b"=") {
+                            push(&mut lines, &rest[..p - 1]);
+                        } else {
+                            push(&mut lines, &rest[..p]);
+                            lines.push(Vec::new());
+                        }
+                        rest = &rest[p + 2..];
+                    }
+                    push(&mut lines, rest);
+                }
+            }
+        }
+    }
+    if matches!(lines.last(), Some(l) if l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn actual_lines(data: &[u8], soft_breaks: bool) -> Vec<Vec<u8>> {
+    let data = if soft_breaks {
+        unwrap_soft_breaks(data)
+    } else {
+        data.to_vec()
+    };
+    let mut lines: Vec<Vec<u8>> = data.split(|&c| c == b'\n').map(|l| l.to_vec()).collect();
+    for line in lines.iter_mut() {
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    if matches!(lines.last(), Some(l) if l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn line_matches(pattern: &[Seg], line: &[u8]) -> Result<(), Mismatch> {
+    match_all(pattern, line, &mut Bindings::default())
+}
+
+fn escape(data: &[u8]) -> String {
+    data.iter()
+        .flat_map(|&c| core::ascii::escape_default(c))
+        .map(char::from)
+        .collect()
+}
+
+fn show_pattern(pattern: &[Seg]) -> String {
+    let mut out = String::new();
+    for seg in pattern {
+        match seg {
+            Seg::Lit(lit) => out.push_str(&escape(lit)),
+            Seg::Field(field) => write!(out, "{{{:?}}}", field).unwrap(),
+        }
+    }
+    out
+}
+
+enum Op {
+    Same(usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Longest-common-subsequence diff of template lines against email lines.
+fn diff_lines(pattern: &[Vec<Seg>], actual: &[Vec<u8>]) -> Vec<Op> {
+    let (n, m) = (pattern.len(), actual.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if line_matches(&pattern[i], &actual[j]).is_ok() {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && line_matches(&pattern[i], &actual[j]).is_ok() {
+            ops.push(Op::Same(j));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Removed(i));
+            i += 1;
+        } else {
+            ops.push(Op::Added(j));
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Number of differing lines and a printable report for one section.
+fn diff_section(name: &str, pattern: &[Vec<Seg>], actual: &[Vec<u8>]) -> (usize, String) {
+    let ops = diff_lines(pattern, actual);
+    let mut changed = 0;
+    let mut out = String::new();
+    writeln!(out, "--- template {}", name).unwrap();
+    writeln!(out, "+++ email {}", name).unwrap();
+
+    let mut k = 0;
+    while k < ops.len() {
+        match ops[k] {
+            Op::Same(j) => {
+                writeln!(out, "  {}", escape(&actual[j])).unwrap();
+                k += 1;
+            }
+            Op::Removed(i) => {
+                changed += 1;
+                writeln!(out, "- {}", show_pattern(&pattern[i])).unwrap();
+                // Pair a removed line with the added line that replaces it.
+                if let Some(Op::Added(j)) = ops.get(k + 1) {
+                    changed += 1;
+                    writeln!(out, "+ {}", escape(&actual[*j])).unwrap();
+                    if let Err(m) = line_matches(&pattern[i], &actual[*j]) {
+                        writeln!(
+                            out,
+                            "  ^ byte {}: expected \"{}\", found \"{}\"",
+                            m.at,
+                            escape(&m.expected[..m.expected.len().min(24)]),
+                            escape(&actual[*j][m.at..actual[*j].len().min(m.at + 24)]),
+                        )
+                        .unwrap();
+                    }
+                    k += 1;
+                }
+                k += 1;
+            }
+            Op::Added(j) => {
+                changed += 1;
+                writeln!(out, "+ {}", escape(&actual[j])).unwrap();
+                k += 1;
+            }
+        }
+    }
+    (changed, out)
+}
+
+/// The template that differs from the email in the fewest lines, and the
+/// diff of its body and header against the email.
+pub fn closest(canonical: &Canonical) -> (&'static Template, String) {
+    let body = actual_lines(&canonical.body, true);
+    let header = actual_lines(&canonical.header, false);
+    let dkim_header = actual_lines(&canonical.dkim.canonical, false);
+
+    template::TEMPLATES
+        .iter()
+        .map(|t| {
+            let (a, body_diff) = diff_section(
+                "body",
+                &pattern_lines(
+                    &[
+                        &[Piece::Lit(b"--")],
+                        t.boundary,
+                        &[Piece::Lit(b"\r\n")],
+                        t.body_head,
+                        t.paragraph,
+                        t.body_tail,
+                        &[Piece::Lit(b"\r\n--")],
+                        t.boundary,
+                        &[Piece::Lit(b"--\r\n")],
+                    ],
+                    true,
+                ),
+                &body,
+            );
+            let (b, header_diff) =
+                diff_section("header", &pattern_lines(&[t.header], false), &header);
+            let (c, dkim_diff) = diff_section(
+                "dkim-signature",
+                &pattern_lines(&[t.dkim_header], false),
+                &dkim_header,
+            );
+            (*t, a + b + c, body_diff + &header_diff + &dkim_diff)
+        })
+        .min_by_key(|(_, changed, _)| *changed)
+        .map(|(t, _, report)| (t, report))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eml::Email;
+    use crate::witness::tests::sample_eml;
+    use fps_types::template::Fields;
+
+    fn canonical(raw: &[u8]) -> Canonical {
+        Email::parse(raw).unwrap().canonicalize().unwrap()
+    }
+
+    #[test]
+    fn sample_is_detected() {
+        let (template, bindings) = detect(&canonical(&sample_eml())).unwrap();
+        assert_eq!(template.id, 1);
+        assert_eq!(bindings.get(Field::Amount), Some(&b"10.00"[..]));
+        assert!(bindings.extra_parts().is_empty());
+    }
+
+    #[test]
+    fn closest_shows_the_line_that_changed() {
+        let raw = sample_eml();
+        let at = find(&raw, b"Yours sincerely,").unwrap();
+        let raw = [&raw[..at], b"Yours faithfully,", &raw[at + 16..]].concat();
+        let canonical = canonical(&raw);
+        assert!(detect(&canonical).is_none());
+
+        let (template, report) = closest(&canonical);
+        assert_eq!(template.id, 1);
+        assert!(report.contains("\n- Yours sincerely,\n+ Yours faithfully,\n"));
+        let changed = report
+            .lines()
+            .filter(|l| l.starts_with("- ") || l.starts_with("+ "))
+            .count();
+        assert_eq!(changed, 2);
+    }
+}
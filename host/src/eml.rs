@@ -0,0 +1,370 @@
+// Parsing of raw .eml files into the bytes that DKIM actually signs,
+// following the "relaxed" canonicalization of RFC 6376, section 3.4.
+
+use fps_types::template::TEMPLATES;
+use std::fmt;
+
+pub struct Header {
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+pub struct DkimSignature {
+    /// The tags as (name, value), with all whitespace removed from the value.
+    pub tags: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The header in relaxed canonicalization with the `b=` value removed.
+    pub canonical: Vec<u8>,
+}
+
+pub struct Email {
+    pub headers: Vec<Header>,
+    pub body: Vec<u8>,
+}
+
+/// The three inputs of the DKIM hashes.
+pub struct Canonical {
+    pub body: Vec<u8>,
+    pub header: Vec<u8>,
+    pub dkim: DkimSignature,
+}
+
+#[derive(Debug)]
+pub enum EmlError {
+    NoHeaderEnd,
+    MalformedHeader(usize),
+    NoDkimSignature,
+    /// None of the signatures is by a signer of a known template; holds the
+    /// `d=` and `s=` of each.
+    UnknownSigner(Vec<(String, String)>),
+    MissingTag(&'static str),
+    MissingHeader(String),
+}
+
+impl fmt::Display for EmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmlError::NoHeaderEnd => write!(f, "no empty line between header and body"),
+            EmlError::MalformedHeader(line) => write!(f, "malformed header at line {}", line),
+            EmlError::NoDkimSignature => write!(f, "no DKIM-Signature header"),
+            EmlError::UnknownSigner(signers) => {
+                write!(f, "no DKIM-Signature by a known signer; found")?;
+                for (domain, selector) in signers {
+                    write!(f, " d={} s={}", domain, selector)?;
+                }
+                Ok(())
+            }
+            EmlError::MissingTag(tag) => write!(f, "DKIM-Signature has no {}= tag", tag),
+            EmlError::MissingHeader(name) => write!(f, "signed header {} not found", name),
+        }
+    }
+}
+
+impl std::error::Error for EmlError {}
+
+fn is_wsp(c: u8) -> bool {
+    c == b' ' || c == b'\t'
+}
+
+/// Files saved by mail clients often use bare LF; DKIM is defined over CRLF.
+fn normalize_newlines(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len() + raw.len() / 32);
+    for (i, &c) in raw.iter().enumerate() {
+        if c == b'\n' && (i == 0 || raw[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn trim(data: &[u8]) -> &[u8] {
+    let start = data.iter().position(|&c| !is_wsp(c)).unwrap_or(data.len());
+    let end = data
+        .iter()
+        .rposition(|&c| !is_wsp(c))
+        .map_or(start, |p| p + 1);
+    &data[start..end]
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Unfold, collapse runs of whitespace into one space, and trim.
+fn relaxed_value(value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut pending_space = false;
+    for &c in value {
+        if c == b'\r' || c == b'\n' || is_wsp(c) {
+            pending_space = true;
+            continue;
+        }
+        if pending_space && !out.is_empty() {
+            out.push(b' ');
+        }
+        pending_space = false;
+        out.push(c);
+    }
+    out
+}
+
+fn relaxed_header(header: &Header) -> Vec<u8> {
+    let mut out = header.name.to_ascii_lowercase();
+    out.push(b':');
+    out.extend_from_slice(&relaxed_value(&header.value));
+    out
+}
+
+pub fn relaxed_body(body: &[u8]) -> Vec<u8> {
+    let mut lines: Vec<Vec<u8>> = Vec::new();
+    for line in body.split(|&c| c == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut out = Vec::with_capacity(line.len());
+        let mut pending_space = false;
+        for &c in line {
+            if is_wsp(c) {
+                pending_space = true;
+                continue;
+            }
+            if pending_space {
+                out.push(b' ');
+            }
+            pending_space = false;
+            out.push(c);
+        }
+        lines.push(out);
+    }
+    while matches!(lines.last(), Some(l) if l.is_empty()) {
+        lines.pop();
+    }
+
+    let mut out = Vec::with_capacity(body.len());
+    for line in lines {
+        out.extend_from_slice(&line);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+impl Email {
+    pub fn parse(raw: &[u8]) -> Result<Email, EmlError> {
+        let raw = normalize_newlines(raw);
+        let split = find(&raw, b"\r\n\r\n").ok_or(EmlError::NoHeaderEnd)?;
+        let (head, body) = (&raw[..split + 2], &raw[split + 4..]);
+
+        let mut headers: Vec<Header> = Vec::new();
+        for (i, line) in head.split(|&c| c == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            if is_wsp(line[0]) {
+                let last = headers.last_mut().ok_or(EmlError::MalformedHeader(i + 1))?;
+                last.value.extend_from_slice(b"\r\n");
+                last.value.extend_from_slice(line);
+                continue;
+            }
+            let colon = line
+                .iter()
+                .position(|&c| c == b':')
+                .ok_or(EmlError::MalformedHeader(i + 1))?;
+            headers.push(Header {
+                name: trim(&line[..colon]).to_vec(),
+                value: line[colon + 1..].to_vec(),
+            });
+        }
+
+        Ok(Email {
+            headers,
+            body: body.to_vec(),
+        })
+    }
+
+    /// The DKIM signature made by the signer of a known template.
+    ///
+    /// A relayed email may carry signatures added on the way, often above the
+    /// bank's own, so the first one is not necessarily the right one.
+    pub fn dkim_signature(&self) -> Result<DkimSignature, EmlError> {
+        let mut signatures: Vec<DkimSignature> = self
+            .headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(b"DKIM-Signature"))
+            .map(DkimSignature::parse)
+            .collect();
+        if signatures.is_empty() {
+            return Err(EmlError::NoDkimSignature);
+        }
+
+        let known = |dkim: &DkimSignature| {
+            TEMPLATES.iter().any(|t| {
+                dkim.tag(b"d") == Some(t.dkim_domain) && dkim.tag(b"s") == Some(t.dkim_selector)
+            })
+        };
+        if let Some(i) = signatures.iter().position(known) {
+            return Ok(signatures.swap_remove(i));
+        }
+
+        let lossy = |tag: Option<&[u8]>| String::from_utf8_lossy(tag.unwrap_or(b"")).into_owned();
+        Err(EmlError::UnknownSigner(
+            signatures
+                .iter()
+                .map(|dkim| (lossy(dkim.tag(b"d")), lossy(dkim.tag(b"s"))))
+                .collect(),
+        ))
+    }
+
+    pub fn canonicalize(&self) -> Result<Canonical, EmlError> {
+        let dkim = self.dkim_signature()?;
+        let signed = dkim.tag(b"h").ok_or(EmlError::MissingTag("h"))?;
+
+        // Signed headers are taken from the bottom up, each instance once.
+        let mut used = vec![false; self.headers.len()];
+        let mut header = Vec::<u8>::with_capacity(1024);
+        for name in signed.split(|&c| c == b':') {
+            let found = self
+                .headers
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(i, h)| !used[*i] && h.name.eq_ignore_ascii_case(name));
+            match found {
+                Some((i, h)) => {
+                    used[i] = true;
+                    header.extend_from_slice(&relaxed_header(h));
+                    header.extend_from_slice(b"\r\n");
+                }
+                None => {
+                    return Err(EmlError::MissingHeader(
+                        String::from_utf8_lossy(name).into_owned(),
+                    ))
+                }
+            }
+        }
+
+        Ok(Canonical {
+            body: relaxed_body(&self.body),
+            header,
+            dkim,
+        })
+    }
+}
+
+impl DkimSignature {
+    fn parse(header: &Header) -> DkimSignature {
+        let tags = header
+            .value
+            .split(|&c| c == b';')
+            .filter_map(|tag| {
+                let eq = tag.iter().position(|&c| c == b'=')?;
+                let name = relaxed_value(&tag[..eq]);
+                let value: Vec<u8> = tag[eq + 1..]
+                    .iter()
+                    .copied()
+                    .filter(|&c| !(is_wsp(c) || c == b'\r' || c == b'\n'))
+                    .collect();
+                Some((name, value))
+            })
+            .collect();
+
+        // Keep everything but the signature itself, which is what was signed.
+        let mut canonical = relaxed_header(header);
+        let mut start = header.name.len() + 1;
+        while start < canonical.len() {
+            let end = canonical[start..]
+                .iter()
+                .position(|&c| c == b';')
+                .map_or(canonical.len(), |p| start + p);
+            if let Some(eq) = canonical[start..end].iter().position(|&c| c == b'=') {
+                if trim(&canonical[start..start + eq]) == b"b" {
+                    canonical.drain(start + eq + 1..end);
+                    break;
+                }
+            }
+            start = end + 1;
+        }
+
+        DkimSignature { tags, canonical }
+    }
+
+    pub fn tag(&self, name: &[u8]) -> Option<&[u8]> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::witness::sample;
+    use crate::witness::tests::sample_eml;
+    use fps_types::template::SC_PAY_SEND_MONEY_V1;
+
+    const RELAY_SIGNATURE: &[u8] =
+        b"DKIM-Signature: v=1; a=rsa-sha256; d=relay.example; s=arc1;\r\n\th=From:To; bh=AAAA; b=BBBB\r\n";
+
+    fn replace(data: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+        let at = find(data, from).unwrap();
+        [&data[..at], to, &data[at + from.len()..]].concat()
+    }
+
+    #[test]
+    fn relaxed_header_unfolds_and_collapses_whitespace() {
+        let email = Email::parse(b"SUBJECT :  a \t b\r\n\t c  \r\n\r\n").unwrap();
+        assert_eq!(relaxed_header(&email.headers[0]), b"subject:a b c");
+        assert_eq!(relaxed_body(b"a  \t b \r\n\r\n\r\n"), b"a b\r\n");
+    }
+
+    #[test]
+    fn header_case_and_spacing_do_not_change_the_canonical_header() {
+        let expected = SC_PAY_SEND_MONEY_V1.render_header(&sample());
+        let raw = replace(&sample_eml(), b"Date: ", b"DATE  :\t ");
+        let raw = replace(&raw, b"MIME-Version: 1.0", b"mime-version:1.0   ");
+        let canonical = Email::parse(&raw).unwrap().canonicalize().unwrap();
+        assert_eq!(canonical.header, expected);
+    }
+
+    #[test]
+    fn folded_dkim_signature_is_read_whole() {
+        let canonical = Email::parse(&sample_eml()).unwrap().canonicalize().unwrap();
+        let dkim = &canonical.dkim;
+        assert_eq!(
+            dkim.canonical,
+            SC_PAY_SEND_MONEY_V1.render_dkim_header(&sample())
+        );
+        assert_eq!(dkim.tag(b"s"), Some(&b"k06k22gbledmsml"[..]));
+        let b = dkim.tag(b"b").unwrap();
+        assert_eq!(b.len(), 344);
+        assert!(!b.iter().any(|&c| is_wsp(c) || c == b'\r' || c == b'\n'));
+    }
+
+    #[test]
+    fn the_signature_of_a_known_signer_is_chosen() {
+        let raw = [RELAY_SIGNATURE, &sample_eml()].concat();
+        let email = Email::parse(&raw).unwrap();
+        let dkim = email.dkim_signature().unwrap();
+        assert_eq!(dkim.tag(b"d"), Some(&b"sc.com"[..]));
+
+        let raw = replace(&raw, b"d=sc.com;", b"d=sc.example;");
+        match Email::parse(&raw).unwrap().dkim_signature() {
+            Err(EmlError::UnknownSigner(signers)) => assert_eq!(
+                signers,
+                [
+                    ("relay.example".to_string(), "arc1".to_string()),
+                    ("sc.example".to_string(), "k06k22gbledmsml".to_string()),
+                ]
+            ),
+            _ => panic!("expected UnknownSigner"),
+        }
+    }
+
+    #[test]
+    fn no_signature_is_an_error() {
+        let email = Email::parse(b"From: a@b.c\r\n\r\nbody\r\n").unwrap();
+        assert!(matches!(
+            email.dkim_signature(),
+            Err(EmlError::NoDkimSignature)
+        ));
+    }
+}
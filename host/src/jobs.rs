@@ -0,0 +1,247 @@
+// A queue of proving jobs kept in SQLite, so that the jobs and their
+// receipts survive a restart of the service.
+//
+// Every thread opens its own `JobStore` on the same file. A job is claimed
+// in an immediate transaction, so two workers never get the same one.
+// The secrets of a job are stored in the clear: the database must be kept
+// as private as the emails themselves.
+
+use fps_types::{ProofParams, Secrets, Witness};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use serde::de::DeserializeOwned;
+use std::path::Path;
+use std::time::Duration;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY,
+    status TEXT NOT NULL,
+    witness TEXT NOT NULL,
+    params TEXT NOT NULL,
+    secrets TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    started INTEGER,
+    finished INTEGER,
+    error TEXT,
+    journal BLOB,
+    receipt BLOB
+)";
+
+const COLUMNS: &str =
+    "id, status, witness, params, secrets, created, started, finished, error, journal";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Queued => "queued",
+            Status::Running => "running",
+            Status::Done => "done",
+            Status::Failed => "failed",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Status> {
+        [
+            Status::Queued,
+            Status::Running,
+            Status::Done,
+            Status::Failed,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == text)
+    }
+}
+
+pub struct Job {
+    pub id: i64,
+    pub status: Status,
+    pub witness: Witness,
+    pub params: ProofParams,
+    pub secrets: Secrets,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub started: Option<u64>,
+    pub finished: Option<u64>,
+    /// Why the job failed.
+    pub error: Option<String>,
+    /// The journal of the receipt, once done.
+    pub journal: Option<Vec<u8>>,
+}
+
+fn json<T: DeserializeOwned>(row: &Row, idx: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(idx)?;
+    serde_json::from_str(&text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+fn time(row: &Row, idx: usize) -> rusqlite::Result<Option<u64>> {
+    Ok(row.get::<_, Option<i64>>(idx)?.map(|t| t as u64))
+}
+
+impl Job {
+    /// From a row of `COLUMNS`.
+    fn from_row(row: &Row) -> rusqlite::Result<Job> {
+        let status: String = row.get(1)?;
+        let status = Status::parse(&status).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                1,
+                Type::Text,
+                format!("unknown status {:?}", status).into(),
+            )
+        })?;
+        Ok(Job {
+            id: row.get(0)?,
+            status,
+            witness: json(row, 2)?,
+            params: json(row, 3)?,
+            secrets: json(row, 4)?,
+            created: row.get::<_, i64>(5)? as u64,
+            started: time(row, 6)?,
+            finished: time(row, 7)?,
+            error: row.get(8)?,
+            journal: row.get(9)?,
+        })
+    }
+}
+
+pub struct JobStore {
+    conn: Connection,
+}
+
+impl JobStore {
+    /// Open the store at `path`, creating it if it does not exist.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(10))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.execute(SCHEMA, [])?;
+        Ok(JobStore { conn })
+    }
+
+    /// Put the jobs that were running when the service stopped back in the
+    /// queue, and return how many there were.
+    pub fn requeue_running(&self) -> rusqlite::Result<usize> {
+        self.conn.execute(
+            "UPDATE jobs SET status = 'queued', started = NULL WHERE status = 'running'",
+            [],
+        )
+    }
+
+    pub fn enqueue(
+        &self,
+        witness: &Witness,
+        params: &ProofParams,
+        secrets: &Secrets,
+    ) -> rusqlite::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO jobs (status, witness, params, secrets, created)
+             VALUES ('queued', ?1, ?2, ?3, ?4)",
+            params![
+                serde_json::to_string(witness).unwrap(),
+                serde_json::to_string(params).unwrap(),
+                serde_json::to_string(secrets).unwrap(),
+                crate::now() as i64,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get(&self, id: i64) -> rusqlite::Result<Option<Job>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM jobs WHERE id = ?1", COLUMNS),
+                [id],
+                Job::from_row,
+            )
+            .optional()
+    }
+
+    /// The receipt file of a job that is done.
+    pub fn receipt(&self, id: i64) -> rusqlite::Result<Option<Vec<u8>>> {
+        Ok(self
+            .conn
+            .query_row("SELECT receipt FROM jobs WHERE id = ?1", [id], |row| {
+                row.get::<_, Option<Vec<u8>>>(0)
+            })
+            .optional()?
+            .flatten())
+    }
+
+    /// Mark the oldest queued job as running and return it.
+    ///
+    /// A job whose row no longer decodes, e.g. because `Witness` changed
+    /// since it was queued, is marked as failed instead, so that it does not
+    /// block the jobs behind it.
+    pub fn claim(&mut self) -> rusqlite::Result<Option<Job>> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        loop {
+            let id: Option<i64> = tx
+                .query_row(
+                    "SELECT id FROM jobs WHERE status = 'queued' ORDER BY id LIMIT 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(id) = id else {
+                tx.commit()?;
+                return Ok(None);
+            };
+            let now = crate::now();
+            let job = tx.query_row(
+                &format!("SELECT {} FROM jobs WHERE id = ?1", COLUMNS),
+                [id],
+                Job::from_row,
+            );
+            match job {
+                Ok(mut job) => {
+                    tx.execute(
+                        "UPDATE jobs SET status = 'running', started = ?2 WHERE id = ?1",
+                        params![id, now as i64],
+                    )?;
+                    tx.commit()?;
+                    job.status = Status::Running;
+                    job.started = Some(now);
+                    return Ok(Some(job));
+                }
+                Err(
+                    e @ (rusqlite::Error::FromSqlConversionFailure(..)
+                    | rusqlite::Error::InvalidColumnType(..)),
+                ) => {
+                    tx.execute(
+                        "UPDATE jobs SET status = 'failed', finished = ?2, error = ?3
+                         WHERE id = ?1",
+                        params![id, now as i64, format!("the job cannot be read: {}", e)],
+                    )?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn finish(&self, id: i64, journal: &[u8], receipt: &[u8]) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE jobs SET status = 'done', finished = ?2, journal = ?3, receipt = ?4
+             WHERE id = ?1",
+            params![id, crate::now() as i64, journal, receipt],
+        )?;
+        Ok(())
+    }
+
+    pub fn fail(&self, id: i64, error: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE jobs SET status = 'failed', finished = ?2, error = ?3 WHERE id = ?1",
+            params![id, crate::now() as i64, error],
+        )?;
+        Ok(())
+    }
+}
@@ -1,70 +1,954 @@
-use core::str::FromStr;
-use methods::{METHOD_ELF, METHOD_ID};
-use num_bigint_dig::BigUint;
-use risc0_zkvm::{default_prover, ExecutorEnv};
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize)]
-pub struct Witness {
-    pub comment_line: Vec<u8>,
-    pub amount: Vec<u8>,
-    pub name: Vec<u8>,
-    pub email: Vec<u8>,
-    pub date_body: Vec<u8>,
-    pub date_head: Vec<u8>,
-    pub receiver: Vec<u8>,
-    pub message_id: Vec<u8>,
-    pub dkim_timestamp: Vec<u8>,
-    pub bh_base64: Vec<u8>,
-    pub receipt_number: Vec<u8>,
-    pub signature_mont: Vec<u8>,
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use fps_types::aggregate::{AggregateOutput, AggregateParams, AGGREGATE_VERSION};
+use fps_types::amount::{AmountDisclosure, AmountPredicate};
+use fps_types::cycles::CycleReport;
+use fps_types::date::FreshnessWindow;
+use fps_types::disclosure;
+use fps_types::sum::{SumOutput, SumParams, SUM_VERSION};
+use fps_types::template::{Field, Fields};
+use fps_types::{mask, validate};
+use fps_types::{ProofParams, PublicOutput, Secrets, Witness, JOURNAL_VERSION};
+use methods::{AGGREGATE_ELF, AGGREGATE_ID, METHOD_ELF, METHOD_ID, SUM_ELF, SUM_ID};
+use nullifier::NullifierRegistry;
+use receipt::{Format, Guest, ReceiptFile};
+use risc0_zkvm::{default_executor, default_prover, ExecutorEnv, Receipt};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+mod batch;
+
+mod detect;
+
+mod eml;
+
+mod jobs;
+
+mod nullifier;
+
+mod receipt;
+
+mod report;
+
+mod service;
+
+mod witness;
+
+/// The guest rejected the input, or a receipt did not verify.
+const EXIT_REJECTED: i32 = 1;
+/// An input could not be read or is not what it should be. 2 is left to
+/// clap, which exits with it on a usage error.
+const EXIT_INPUT: i32 = 3;
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
 }
 
-fn main() {
-    let comment_line = b"2772651_343290592.1700310140503";
-    let amount = b"10.00";
-    let name = b"CHEN W******";
-    let email = b"w********@chenweikeng.com";
-    let date_body = b"18/11/2023";
-    let date_head = b"Sat, 18 Nov 2023 20:22:20 +0800";
-    let receiver = b"\"WEIKENG@CHENWEIKENG.COM\" <WEIKENG@CHENWEIKENG.COM>";
-    let message_id = b"<101958940.2772652.1700310140503.JavaMail.1000830000@hk-boa-15-5f8fl>";
-    let dkim_timestamp = b"1700310181";
-    let bh_base64 = b"hJ/+UNkf1BHOUMaYhrzDzD3adraujFmKjZajNWOLYT4=";
-    let receipt_number = b"2311-182022218700";
-
-    let signature = BigUint::from_str("10625512586255484529905913055518690041367016184139603208541053043067627026596170923852906588557400770118440711602759466843726969481836140444332240137316710742516639602143210812428949677883618101389359047511373992985514651735606880700898915238753116119729027152984350103669187052930077764847488756122702904657364560056547986875486100784117240746834017001781152776378161718685359034677365279732516706989700441393316707925352884322083934071736978776879652906169104284627863089773539963210536556881828553404441522346601372075574798832939215784948057194166388978217945842562118998420736255452138960283685002443406516028414").unwrap();
-    let n = BigUint::from_str("22181287481343866536926164726351287326530456851865740940302258624292918842046294265777588938243700158420966504059481663514441470940350196901315671547076005234970874435909476092497483551273288093189364709035514616037071211153823131905024178182878201024915500433097297265826798822817484748700216324125712309789054401424099125210527384783630725436400275931057214172116786047287671841780210364049070913138670556222022084829676330760494242212963241225957072902927387309610872757297833214507573774777580968710434530894604337230857277368168283766335313014325255932691808839056156851505239358105335763858378332776753927248103").unwrap();
-    let r = BigUint::from_str("10135718589967140763788711962318664633913645817849743091828086803231736296821596627419612473279213305267751456862416355979678088210140724193772480839372277885660002931391519999252714199116364013606692929348552952239721007488796624256813916155597969445666145418939007777060777068723581059907336074998218075732859931965569217210157590002933844058455900104269107885961019612043354350928249950101187679725506560503921518888785526597104108939338404678446624710305899921616252927412986995217583326949350354759244011686052360704188719900184714871880212152064181402851793296377072753140079120499616384335177520834305668982553").unwrap();
-
-    let mut signature_mont = [0u8; 256];
-    signature_mont.copy_from_slice(&(signature * r % n).to_bytes_le());
-
-    let witness = Witness {
-        comment_line: comment_line.to_vec(),
-        amount: amount.to_vec(),
-        name: name.to_vec(),
-        email: email.to_vec(),
-        date_body: date_body.to_vec(),
-        date_head: date_head.to_vec(),
-        receiver: receiver.to_vec(),
-        message_id: message_id.to_vec(),
-        dkim_timestamp: dkim_timestamp.to_vec(),
-        bh_base64: bh_base64.to_vec(),
-        receipt_number: receipt_number.to_vec(),
-        signature_mont: signature_mont.to_vec(),
+/// Seconds since the Unix epoch.
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[derive(Parser)]
+#[command(
+    about = "Prove FPS payments from the bank's receipt emails",
+    after_help = "Exit status: 0 on success, 1 if the guest rejected the input or a receipt \
+                  did not verify, 2 on a usage error, 3 if an input could not be read or is \
+                  not what it should be."
+)]
+struct Cli {
+    /// Print the result as JSON on stdout.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Match an .eml file against the templates and write the witness.
+    Extract {
+        email: PathBuf,
+        /// Where to write the witness; stdout if omitted.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Run the guest without proving, which takes seconds rather than
+    /// minutes, and print the journal and the number of cycles.
+    Execute(Inputs),
+    /// Prove a witness and write the receipt.
+    Prove {
+        #[command(flatten)]
+        inputs: Inputs,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Prove the total paid to one payee over many witnesses, in a single
+    /// run of the sum guest.
+    Sum {
+        /// The witnesses as written by `extract`.
+        #[arg(required = true)]
+        witnesses: Vec<PathBuf>,
+        #[command(flatten)]
+        selection: Selection,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Aggregate receipt files of the main guest into one receipt of their
+    /// total, without proving the emails again.
+    Aggregate {
+        /// Receipt files as written by `prove`.
+        #[arg(required = true)]
+        receipts: Vec<PathBuf>,
+        #[command(flatten)]
+        selection: Selection,
+        /// Prove only that the total satisfies this predicate, as JSON in
+        /// minor units, e.g. '{"AtLeast":10000}'; revealed if omitted.
+        #[arg(long, value_parser = parse_predicate)]
+        total: Option<AmountPredicate>,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Verify a receipt file and print its journal.
+    Verify {
+        receipt: PathBuf,
+        /// The image id to trust, in hex; that of the guest in this build
+        /// if omitted.
+        #[arg(long, value_parser = receipt::parse_image_id)]
+        image_id: Option<[u32; 8]>,
+        /// For an aggregate, the image id that the receipts inside must be
+        /// of, in hex; that of the main guest in this build if omitted.
+        #[arg(long, value_parser = receipt::parse_image_id)]
+        inner_image_id: Option<[u32; 8]>,
+        /// SQLite registry of used nullifiers; the receipt is rejected if
+        /// its nullifier is already there, and added otherwise.
+        #[arg(long)]
+        nullifiers: Option<PathBuf>,
+    },
+    /// Print the journal of a receipt file without verifying it.
+    Inspect { receipt: PathBuf },
+    /// Serve proving jobs over HTTP, queued in an SQLite database.
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        #[arg(long, default_value = "jobs.sqlite")]
+        db: PathBuf,
+        /// How many jobs are proven at the same time. Each takes several
+        /// gigabytes of memory.
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+    },
+    /// Prove every .eml in a directory and write the receipts and a
+    /// manifest; an interrupted batch resumes from the manifest.
+    Batch {
+        emails: PathBuf,
+        /// The directory of the receipts and the manifest.
+        #[arg(short, long = "output")]
+        out: PathBuf,
+        /// ProofParams as JSON, for every email; the default policy if
+        /// omitted.
+        #[arg(long)]
+        params: Option<PathBuf>,
+        /// How many emails are proven at the same time.
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+        /// The memory, in GiB, that the workers may take together; proving
+        /// uses smaller segments to stay under it.
+        #[arg(long)]
+        memory_limit: Option<u64>,
+        /// Prove again the emails that failed in an earlier run.
+        #[arg(long)]
+        retry_failed: bool,
+    },
+    /// Prove the receipt embedded in the host.
+    Sample {
+        #[arg(value_enum, default_value_t = SampleKind::Single)]
+        kind: SampleKind,
+        #[arg(long)]
+        nullifiers: Option<PathBuf>,
+        #[command(flatten)]
+        output: Option<Output>,
+    },
+}
+
+#[derive(Args)]
+struct Inputs {
+    /// The witness as written by `extract`.
+    witness: PathBuf,
+    /// ProofParams as JSON; the default policy if omitted.
+    #[arg(long)]
+    params: Option<PathBuf>,
+    /// Secrets as JSON; a random salt seed if omitted.
+    #[arg(long)]
+    secrets: Option<PathBuf>,
+}
+
+/// The receipts that a total is over.
+#[derive(Args)]
+struct Selection {
+    /// The payee email, as masked by the bank, that every receipt must show.
+    #[arg(long)]
+    payee: String,
+    /// Earliest `Date:` header, in seconds since the Unix epoch.
+    #[arg(long)]
+    from: Option<u64>,
+    /// Latest `Date:` header, in seconds since the Unix epoch.
+    #[arg(long)]
+    to: Option<u64>,
+    /// The application id of the nullifiers.
+    #[arg(long, default_value = "")]
+    app_id: String,
+}
+
+impl Selection {
+    fn period(&self) -> FreshnessWindow {
+        FreshnessWindow {
+            not_before: self.from,
+            not_after: self.to,
+        }
+    }
+}
+
+fn parse_predicate(text: &str) -> Result<AmountPredicate, String> {
+    serde_json::from_str(text).map_err(|e| e.to_string())
+}
+
+#[derive(Args)]
+struct Output {
+    /// Where to write the receipt.
+    #[arg(short, long = "output")]
+    path: PathBuf,
+    /// Binary unless the path ends in .json.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+}
+
+impl Output {
+    fn save(&self, guest: Guest, receipt: Receipt) -> Result<(), Failure> {
+        let format = self.format.unwrap_or_else(|| Format::for_path(&self.path));
+        ReceiptFile::new(guest, receipt)
+            .save(&self.path, format)
+            .map_err(|e| Failure::input(format!("{}: {}", self.path.display(), e)))
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SampleKind {
+    Single,
+    Sum,
+    Aggregate,
+}
+
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl Failure {
+    fn input(message: impl fmt::Display) -> Self {
+        Failure {
+            code: EXIT_INPUT,
+            message: message.to_string(),
+        }
+    }
+
+    fn rejected(message: impl fmt::Display) -> Self {
+        Failure {
+            code: EXIT_REJECTED,
+            message: message.to_string(),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Failure> {
+    std::fs::read(path).map_err(|e| Failure::input(format!("{}: {}", path.display(), e)))
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), Failure> {
+    std::fs::write(path, data).map_err(|e| Failure::input(format!("{}: {}", path.display(), e)))
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Failure> {
+    serde_json::from_slice(&read(path)?)
+        .map_err(|e| Failure::input(format!("{}: {}", path.display(), e)))
+}
+
+/// The salt that opens the commitment to `field`.
+fn salt(seed: &[u8; 32], field: Field) -> [u8; 32] {
+    Sha256::digest(disclosure::salt_preimage(seed, field)).into()
+}
+
+/// Check the full name and email against the masks in the receipt, as the
+/// guest will, so that a mismatch is reported before proving.
+fn check_unmasked(witness: &Witness, secrets: &Secrets) -> Result<(), Field> {
+    for (field, full) in [
+        (Field::Name, &secrets.full_name),
+        (Field::Email, &secrets.full_email),
+    ] {
+        if let Some(full) = full {
+            if !mask::consistent(witness.field(field), full) {
+                return Err(field);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn random_secrets() -> Secrets {
+    Secrets {
+        salt_seed: rand::random(),
+        full_name: None,
+        full_email: None,
+    }
+}
+
+/// Check the witness and the secrets as far as the host can.
+fn check_inputs(witness: &Witness, secrets: &Secrets) -> Result<(), Failure> {
+    if let Err(field) = validate::check(witness) {
+        return Err(Failure::input(format!(
+            "{:?} does not have the shape the guest accepts",
+            field
+        )));
+    }
+    if let Err(field) = check_unmasked(witness, secrets) {
+        return Err(Failure::input(format!(
+            "the full {:?} does not match the mask",
+            field
+        )));
+    }
+    Ok(())
+}
+
+/// The witness, params and secrets, checked as far as the host can.
+fn load(inputs: &Inputs) -> Result<(Witness, ProofParams, Secrets), Failure> {
+    let witness: Witness = read_json(&inputs.witness)?;
+    let params = match &inputs.params {
+        Some(path) => read_json(path)?,
+        None => ProofParams::default(),
+    };
+    let secrets = match &inputs.secrets {
+        Some(path) => read_json(path)?,
+        None => random_secrets(),
     };
+    check_inputs(&witness, &secrets)?;
+    Ok((witness, params, secrets))
+}
+
+/// The guest writes its `CycleReport` to `stdout`. Segments are of 2^po2
+/// cycles, the default of risc0 if `None`.
+fn env<'a>(
+    witness: &Witness,
+    params: &ProofParams,
+    secrets: &Secrets,
+    stdout: impl Write + 'a,
+    segment_po2: Option<u32>,
+) -> ExecutorEnv<'a> {
+    let mut builder = ExecutorEnv::builder();
+    builder
+        .write(witness)
+        .unwrap()
+        .write(params)
+        .unwrap()
+        .write(secrets)
+        .unwrap()
+        .stdout(stdout);
+    if let Some(po2) = segment_po2 {
+        builder.segment_limit_po2(po2);
+    }
+    builder.build().unwrap()
+}
+
+/// The journal of one of the guests, which starts with its layout version.
+trait Journal: DeserializeOwned {
+    const VERSION: u32;
+
+    fn version(&self) -> u32;
+}
+
+impl Journal for PublicOutput {
+    const VERSION: u32 = JOURNAL_VERSION;
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Journal for SumOutput {
+    const VERSION: u32 = SUM_VERSION;
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+impl Journal for AggregateOutput {
+    const VERSION: u32 = AGGREGATE_VERSION;
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+fn decode<T: Journal>(journal: &risc0_zkvm::Journal) -> Result<T, Failure> {
+    let output: T = journal
+        .decode()
+        .map_err(|e| Failure::input(format!("journal: {}", e)))?;
+    if output.version() != T::VERSION {
+        return Err(Failure::input(format!(
+            "journal version {}, expected {}",
+            output.version(),
+            T::VERSION
+        )));
+    }
+    Ok(output)
+}
+
+fn prove_method(
+    witness: &Witness,
+    params: &ProofParams,
+    secrets: &Secrets,
+    segment_po2: Option<u32>,
+) -> Result<Receipt, Failure> {
+    let timer = std::time::Instant::now();
+    let env = env(witness, params, secrets, io::sink(), segment_po2);
+    let receipt = default_prover()
+        .prove(env, METHOD_ELF)
+        .map_err(|e| Failure::rejected(format!("{:#}", e)))?;
+    eprintln!("time: {}", timer.elapsed().as_secs_f64());
+    receipt.verify(METHOD_ID).map_err(Failure::rejected)?;
+    Ok(receipt)
+}
+
+/// Prove and check that the journal is the one asked for.
+fn prove_journal(
+    witness: &Witness,
+    params: &ProofParams,
+    secrets: &Secrets,
+    segment_po2: Option<u32>,
+) -> Result<(Receipt, PublicOutput), Failure> {
+    let receipt = prove_method(witness, params, secrets, segment_po2)?;
+    let journal: PublicOutput = decode(&receipt.journal)?;
+    if journal.policy_hash != <[u8; 32]>::from(Sha256::digest(params.policy.encode())) {
+        return Err(Failure::rejected(
+            "the journal has another disclosure policy",
+        ));
+    }
+    Ok((receipt, journal))
+}
+
+/// The salts of the committed fields, which the prover needs to open them.
+fn salts(params: &ProofParams, secrets: &Secrets) -> Vec<(Field, [u8; 32])> {
+    params
+        .policy
+        .committed()
+        .into_iter()
+        .map(|field| (field, salt(&secrets.salt_seed, field)))
+        .collect()
+}
+
+/// Print `{"journal": journal}` with the fields of `extra` as JSON, or call
+/// `print`.
+fn show_with(json: bool, journal: Value, extra: Value, print: impl FnOnce()) {
+    if json {
+        let mut value = json!({ "journal": journal });
+        if let Value::Object(extra) = extra {
+            value.as_object_mut().unwrap().extend(extra);
+        }
+        println!("{}", value);
+    } else {
+        print();
+    }
+}
+
+/// The salts by field name, in hex.
+fn salts_json(salts: &[(Field, [u8; 32])]) -> Value {
+    salts
+        .iter()
+        .map(|(field, salt)| (format!("{:?}", field), Value::from(hex(salt))))
+        .collect()
+}
+
+fn show(json: bool, output: &PublicOutput, salts: &[(Field, [u8; 32])], mut extra: Value) {
+    if !salts.is_empty() {
+        extra["salts"] = salts_json(salts);
+    }
+    show_with(json, report::output_json(output), extra, || {
+        report::print_output(output);
+        for (field, salt) in salts {
+            println!("salt to open {:?}: {}", field, hex(salt));
+        }
+    });
+}
+
+fn check_nullifier(path: Option<&Path>, output: &PublicOutput) -> Result<(), Failure> {
+    if let Some(path) = path {
+        let mut registry = NullifierRegistry::open(path).map_err(Failure::input)?;
+        registry
+            .insert(&output.nullifier)
+            .map_err(Failure::rejected)?;
+    }
+    Ok(())
+}
+
+fn extract(json: bool, path: &Path, output: Option<&Path>) -> Result<(), Failure> {
+    let raw = read(path)?;
+    let extracted = witness::from_eml(&raw).map_err(|e| {
+        if let witness::ExtractError::NoTemplate(_, report) = &e {
+            eprint!("{}", report);
+        }
+        Failure::input(e)
+    })?;
+    let witness::Extracted {
+        template,
+        bindings,
+        witness,
+    } = extracted;
+    let encoded = serde_json::to_vec(&witness).unwrap();
+
+    let Some(output) = output else {
+        println!("{}", String::from_utf8_lossy(&encoded));
+        return Ok(());
+    };
+    write(output, &encoded)?;
+
+    if json {
+        let fields: serde_json::Map<String, Value> = bindings
+            .iter()
+            .map(|(field, value)| {
+                let value = String::from_utf8_lossy(value).into_owned();
+                (format!("{:?}", field), Value::from(value))
+            })
+            .collect();
+        println!(
+            "{}",
+            json!({
+                "template_id": template.id,
+                "fields": fields,
+                "extra_parts": bindings.extra_parts().len(),
+            })
+        );
+    } else {
+        println!("template: {}", template.id);
+        for (field, value) in bindings.iter() {
+            println!("{:?}: {}", field, String::from_utf8_lossy(value));
+        }
+        for part in bindings.extra_parts() {
+            println!("extra part: {} bytes", part.len());
+        }
+    }
+    Ok(())
+}
+
+fn execute(json: bool, inputs: &Inputs) -> Result<(), Failure> {
+    let (witness, params, secrets) = load(inputs)?;
+    let timer = std::time::Instant::now();
+    let mut stdout = Vec::new();
+    // A panic in the guest is an error whose chain ends with its message,
+    // hence the alternate form.
+    let session = default_executor()
+        .execute(
+            env(&witness, &params, &secrets, &mut stdout, None),
+            METHOD_ELF,
+        )
+        .map_err(|e| Failure::rejected(format!("{:#}", e)))?;
+    eprintln!("time: {}", timer.elapsed().as_secs_f64());
+
+    let output: PublicOutput = decode(&session.journal)?;
+    let phases: CycleReport = risc0_zkvm::serde::from_slice(&stdout)
+        .map_err(|e| Failure::input(format!("cycle report: {}", e)))?;
+    let segments = session.segments.len();
+    let cycles: u64 = session.segments.iter().map(|s| u64::from(s.cycles)).sum();
+    let salts = salts(&params, &secrets);
+    if json {
+        let stats = json!({
+            "exit_code": format!("{:?}", session.exit_code),
+            "segments": segments,
+            "cycles": cycles,
+            "phases": phases,
+        });
+        show(true, &output, &salts, stats);
+    } else {
+        show(false, &output, &salts, Value::Null);
+        println!("exit code: {:?}", session.exit_code);
+        println!("segments: {}, cycles: {}", segments, cycles);
+        report::print_cycles(&phases);
+    }
+    Ok(())
+}
+
+fn prove(json: bool, inputs: &Inputs, output: &Output) -> Result<(), Failure> {
+    let (witness, params, secrets) = load(inputs)?;
+    let (receipt, journal) = prove_journal(&witness, &params, &secrets, None)?;
+    output.save(Guest::Method, receipt)?;
+    show(json, &journal, &salts(&params, &secrets), Value::Null);
+    Ok(())
+}
+
+fn load_receipt(path: &Path) -> Result<ReceiptFile, Failure> {
+    ReceiptFile::load(path).map_err(|e| Failure::input(format!("{}: {}", path.display(), e)))
+}
+
+/// Print the journal of a receipt file according to its guest, after
+/// checking the nullifier if it is one of the main guest.
+fn show_file(
+    json: bool,
+    file: &ReceiptFile,
+    verified: bool,
+    nullifiers: Option<&Path>,
+) -> Result<(), Failure> {
+    if nullifiers.is_some() && file.guest != Guest::Method {
+        return Err(Failure::input(
+            "only receipts of the main guest have a nullifier",
+        ));
+    }
+    if !json {
+        println!(
+            "{:?} receipt of image {}, written {} by host {}",
+            file.guest,
+            receipt::image_hex(&file.image_id),
+            file.created,
+            file.host_version
+        );
+        println!("verified: {}", verified);
+    }
+    let meta = json!({
+        "verified": verified,
+        "guest": file.guest,
+        "image_id": receipt::image_hex(&file.image_id),
+        "created": file.created,
+        "host_version": file.host_version,
+    });
+    match file.guest {
+        Guest::Method => {
+            let output: PublicOutput = decode(&file.receipt.journal)?;
+            check_nullifier(nullifiers, &output)?;
+            show(json, &output, &[], meta);
+        }
+        Guest::Sum => {
+            let output: SumOutput = decode(&file.receipt.journal)?;
+            show_with(json, report::sum_json(&output), meta, || {
+                report::print_sum(&output)
+            });
+        }
+        Guest::Aggregate => {
+            let output: AggregateOutput = decode(&file.receipt.journal)?;
+            show_with(json, report::aggregate_json(&output), meta, || {
+                report::print_aggregate(&output)
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The aggregate guest verifies its receipts against the image id it is
+/// given, which the prover chooses. Its receipt only says something about
+/// receipts of the main guest if that image id is the one we trust.
+fn check_inner_image(file: &ReceiptFile, inner_image_id: Option<[u32; 8]>) -> Result<(), Failure> {
+    if file.guest != Guest::Aggregate {
+        if inner_image_id.is_some() {
+            return Err(Failure::input(
+                "only aggregate receipts have an inner image id",
+            ));
+        }
+        return Ok(());
+    }
+    let output: AggregateOutput = decode(&file.receipt.journal)?;
+    let trusted = inner_image_id.unwrap_or(METHOD_ID);
+    if output.image_id != trusted {
+        return Err(Failure::rejected(format!(
+            "the aggregate is over receipts of image {}, expected {}",
+            receipt::image_hex(&output.image_id),
+            receipt::image_hex(&trusted)
+        )));
+    }
+    Ok(())
+}
+
+fn verify(
+    json: bool,
+    path: &Path,
+    image_id: Option<[u32; 8]>,
+    inner_image_id: Option<[u32; 8]>,
+    nullifiers: Option<&Path>,
+) -> Result<(), Failure> {
+    let file = load_receipt(path)?;
+    let trusted = image_id.unwrap_or_else(|| file.guest.image_id());
+    if file.image_id != trusted {
+        return Err(Failure::rejected(format!(
+            "the receipt is of image {}, expected {}",
+            receipt::image_hex(&file.image_id),
+            receipt::image_hex(&trusted)
+        )));
+    }
+    file.receipt.verify(trusted).map_err(Failure::rejected)?;
+    check_inner_image(&file, inner_image_id)?;
+    show_file(json, &file, true, nullifiers)
+}
+
+fn inspect(json: bool, path: &Path) -> Result<(), Failure> {
+    show_file(json, &load_receipt(path)?, false, None)
+}
+
+fn prove_sample(
+    json: bool,
+    nullifiers: Option<&Path>,
+    output: Option<&Output>,
+) -> Result<(), Failure> {
+    let witness = witness::sample();
+    let params = ProofParams::default();
+    let secrets = random_secrets();
+
+    let receipt = prove_method(&witness, &params, &secrets, None)?;
+    let journal: PublicOutput = decode(&receipt.journal)?;
+    if let Some(output) = output {
+        output.save(Guest::Method, receipt)?;
+    }
+    show(json, &journal, &salts(&params, &secrets), Value::Null);
+    check_nullifier(nullifiers, &journal)
+}
+
+/// Prove the total of `witnesses`, after checking each as far as the host
+/// can.
+fn prove_sum(
+    json: bool,
+    witnesses: &[Witness],
+    params: &SumParams,
+    output: Option<&Output>,
+) -> Result<(), Failure> {
+    for (i, witness) in witnesses.iter().enumerate() {
+        check_inputs(witness, &random_secrets())
+            .map_err(|e| Failure::input(format!("witness {}: {}", i + 1, e.message)))?;
+        if witness.email != params.payee {
+            return Err(Failure::input(format!(
+                "witness {} is paid to {}",
+                i + 1,
+                String::from_utf8_lossy(&witness.email)
+            )));
+        }
+    }
 
     let env = ExecutorEnv::builder()
-        .write(&witness)
+        .write(&witnesses)
+        .unwrap()
+        .write(params)
         .unwrap()
+        .stdout(io::sink())
         .build()
         .unwrap();
 
-    let prover = default_prover();
+    let receipt = default_prover()
+        .prove(env, SUM_ELF)
+        .map_err(|e| Failure::rejected(format!("{:#}", e)))?;
+    receipt.verify(SUM_ID).map_err(Failure::rejected)?;
 
-    let timer = std::time::Instant::now();
-    let receipt = prover.prove_elf(env, METHOD_ELF).unwrap();
-    println!("time: {}", timer.elapsed().as_secs_f64());
-    receipt.verify(METHOD_ID).unwrap();
+    let journal: SumOutput = decode(&receipt.journal)?;
+    if let Some(output) = output {
+        output.save(Guest::Sum, receipt)?;
+    }
+    show_with(json, report::sum_json(&journal), Value::Null, || {
+        report::print_sum(&journal)
+    });
+    Ok(())
+}
+
+fn sum(
+    json: bool,
+    paths: &[PathBuf],
+    selection: &Selection,
+    output: &Output,
+) -> Result<(), Failure> {
+    let witnesses = paths
+        .iter()
+        .map(|path| read_json(path))
+        .collect::<Result<Vec<Witness>, _>>()?;
+    let params = SumParams {
+        payee: selection.payee.as_bytes().to_vec(),
+        period: selection.period(),
+        app_id: selection.app_id.as_bytes().to_vec(),
+    };
+    prove_sum(json, &witnesses, &params, Some(output))
+}
+
+/// Aggregate the journals of `inner`, each added as an assumption.
+fn prove_aggregate(
+    json: bool,
+    inner: Vec<Receipt>,
+    params: &AggregateParams,
+    output: Option<&Output>,
+) -> Result<(), Failure> {
+    let journals: Vec<Vec<u8>> = inner.iter().map(|r| r.journal.bytes.clone()).collect();
+
+    let mut builder = ExecutorEnv::builder();
+    for receipt in inner {
+        builder.add_assumption(receipt);
+    }
+    let env = builder
+        .write(&journals)
+        .unwrap()
+        .write(params)
+        .unwrap()
+        .stdout(io::sink())
+        .build()
+        .unwrap();
+
+    let receipt = default_prover()
+        .prove(env, AGGREGATE_ELF)
+        .map_err(|e| Failure::rejected(format!("{:#}", e)))?;
+    receipt.verify(AGGREGATE_ID).map_err(Failure::rejected)?;
+
+    let journal: AggregateOutput = decode(&receipt.journal)?;
+    if journal.image_id != METHOD_ID {
+        return Err(Failure::rejected(
+            "the aggregate is not over receipts of the main guest",
+        ));
+    }
+    if let Some(output) = output {
+        output.save(Guest::Aggregate, receipt)?;
+    }
+    show_with(json, report::aggregate_json(&journal), Value::Null, || {
+        report::print_aggregate(&journal)
+    });
+    Ok(())
+}
+
+/// Verify each receipt file against the main guest, then aggregate them.
+fn aggregate(
+    json: bool,
+    paths: &[PathBuf],
+    selection: &Selection,
+    total: Option<AmountPredicate>,
+    output: &Output,
+) -> Result<(), Failure> {
+    let mut inner = Vec::with_capacity(paths.len());
+    for path in paths {
+        let file = load_receipt(path)?;
+        let reject = |why: &str| Failure::rejected(format!("{}: {}", path.display(), why));
+        if file.guest != Guest::Method || file.image_id != METHOD_ID {
+            return Err(reject("not a receipt of the main guest in this build"));
+        }
+        file.receipt
+            .verify(METHOD_ID)
+            .map_err(|e| reject(&e.to_string()))?;
+        let journal: PublicOutput = decode(&file.receipt.journal)?;
+        if journal.app_id != selection.app_id.as_bytes() {
+            return Err(reject("the receipt has another application id"));
+        }
+        inner.push(file.receipt);
+    }
+
+    let params = AggregateParams {
+        image_id: METHOD_ID,
+        app_id: selection.app_id.as_bytes().to_vec(),
+        payee: selection.payee.as_bytes().to_vec(),
+        period: selection.period(),
+        total: total.map_or(AmountDisclosure::Reveal, AmountDisclosure::Predicate),
+    };
+    prove_aggregate(json, inner, &params, Some(output))
+}
+
+/// Prove the sample receipt on its own, then aggregate its journal by
+/// verifying the receipt as an assumption.
+fn prove_sample_aggregate(json: bool, output: Option<&Output>) -> Result<(), Failure> {
+    let witness = witness::sample();
+    let inner = prove_method(&witness, &ProofParams::default(), &random_secrets(), None)?;
+    let params = AggregateParams {
+        image_id: METHOD_ID,
+        app_id: Vec::new(),
+        payee: witness.email.clone(),
+        period: FreshnessWindow::default(),
+        total: AmountDisclosure::Reveal,
+    };
+    prove_aggregate(json, vec![inner], &params, output)
+}
+
+fn run(cli: &Cli) -> Result<(), Failure> {
+    let json = cli.json;
+    match &cli.command {
+        Command::Extract { email, output } => extract(json, email, output.as_deref()),
+        Command::Execute(inputs) => execute(json, inputs),
+        Command::Prove { inputs, output } => prove(json, inputs, output),
+        Command::Sum {
+            witnesses,
+            selection,
+            output,
+        } => sum(json, witnesses, selection, output),
+        Command::Aggregate {
+            receipts,
+            selection,
+            total,
+            output,
+        } => aggregate(json, receipts, selection, *total, output),
+        Command::Verify {
+            receipt,
+            image_id,
+            inner_image_id,
+            nullifiers,
+        } => verify(
+            json,
+            receipt,
+            *image_id,
+            *inner_image_id,
+            nullifiers.as_deref(),
+        ),
+        Command::Inspect { receipt } => inspect(json, receipt),
+        Command::Serve { addr, db, workers } => service::serve(addr, db, *workers),
+        Command::Batch {
+            emails,
+            out,
+            params,
+            workers,
+            memory_limit,
+            retry_failed,
+        } => {
+            let params = match params {
+                Some(path) => read_json(path)?,
+                None => ProofParams::default(),
+            };
+            let options = batch::Options {
+                workers: *workers,
+                memory_limit: *memory_limit,
+                params,
+                retry_failed: *retry_failed,
+            };
+            batch::batch(json, emails, out, options)
+        }
+        Command::Sample {
+            kind,
+            nullifiers,
+            output,
+        } => match kind {
+            SampleKind::Single => prove_sample(json, nullifiers.as_deref(), output.as_ref()),
+            SampleKind::Sum => {
+                let witness = witness::sample();
+                let params = SumParams {
+                    payee: witness.email.clone(),
+                    period: FreshnessWindow::default(),
+                    app_id: Vec::new(),
+                };
+                prove_sum(json, &[witness], &params, output.as_ref())
+            }
+            SampleKind::Aggregate => prove_sample_aggregate(json, output.as_ref()),
+        },
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(failure) = run(&cli) {
+        if cli.json {
+            println!("{}", json!({ "error": failure.message }));
+        } else {
+            eprintln!("error: {}", failure.message);
+        }
+        std::process::exit(failure.code);
+    }
 }
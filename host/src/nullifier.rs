@@ -0,0 +1,109 @@
+// A registry of the nullifiers of receipts that were already accepted, so
+// that the same payment cannot be redeemed twice.
+//
+// The registry is an SQLite database with the nullifier as primary key.
+// Checking and recording a nullifier is a single insert, so two processes
+// verifying the same receipt at once cannot both accept it.
+
+use crate::hex;
+use rusqlite::{params, Connection, ErrorCode};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS nullifiers (
+    nullifier BLOB PRIMARY KEY,
+    accepted INTEGER NOT NULL
+)";
+
+#[derive(Debug)]
+pub enum RegistryError {
+    AlreadySeen([u8; 32]),
+    Db(rusqlite::Error),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::AlreadySeen(n) => write!(f, "nullifier {} was already used", hex(n)),
+            RegistryError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<rusqlite::Error> for RegistryError {
+    fn from(e: rusqlite::Error) -> Self {
+        RegistryError::Db(e)
+    }
+}
+
+pub struct NullifierRegistry {
+    conn: Connection,
+}
+
+impl NullifierRegistry {
+    /// Open the registry at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(10))?;
+        conn.execute(SCHEMA, [])?;
+        Ok(NullifierRegistry { conn })
+    }
+
+    /// Record `nullifier`, or fail if it was recorded before.
+    pub fn insert(&mut self, nullifier: &[u8; 32]) -> Result<(), RegistryError> {
+        let inserted = self.conn.execute(
+            "INSERT INTO nullifiers (nullifier, accepted) VALUES (?1, ?2)",
+            params![&nullifier[..], crate::now() as i64],
+        );
+        match inserted {
+            Ok(_) => Ok(()),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == ErrorCode::ConstraintViolation =>
+            {
+                Err(RegistryError::AlreadySeen(*nullifier))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::witness::sample;
+    use fps_types::nullifier::preimage;
+    use sha2::{Digest, Sha256};
+
+    fn nullifier(app_id: &[u8]) -> [u8; 32] {
+        let witness = sample();
+        Sha256::digest(preimage(
+            app_id,
+            &witness.message_id,
+            &witness.receipt_number,
+        ))
+        .into()
+    }
+
+    #[test]
+    fn second_insert_is_already_seen() {
+        let mut registry = NullifierRegistry::open(":memory:").unwrap();
+        let shop = nullifier(b"shop");
+        registry.insert(&shop).unwrap();
+        match registry.insert(&shop) {
+            Err(RegistryError::AlreadySeen(seen)) => assert_eq!(seen, shop),
+            other => panic!("expected AlreadySeen, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_app_ids_do_not_collide() {
+        let mut registry = NullifierRegistry::open(":memory:").unwrap();
+        registry.insert(&nullifier(b"shop")).unwrap();
+        registry.insert(&nullifier(b"other shop")).unwrap();
+        registry.insert(&nullifier(b"")).unwrap();
+        assert!(registry.insert(&nullifier(b"other shop")).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+// The file that a receipt is kept in between proving and verifying.
+//
+// A receipt file holds the receipt, i.e. the seal and the journal, with the
+// image id it claims to be from and when and by which host it was written.
+// It is either JSON, for people and scripts, or bincode after a magic
+// number, which is much smaller. `load` tells the two apart by the first
+// bytes, so a file can be renamed freely.
+
+use crate::hex;
+use clap::ValueEnum;
+use methods::{AGGREGATE_ID, METHOD_ID, SUM_ID};
+use risc0_zkvm::Receipt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"FPSR";
+
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The guest that produced the receipt, which decides how the journal is
+/// decoded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Guest {
+    Method,
+    Sum,
+    Aggregate,
+}
+
+impl Guest {
+    /// The image id of this guest in the current build.
+    pub fn image_id(self) -> [u32; 8] {
+        match self {
+            Guest::Method => METHOD_ID,
+            Guest::Sum => SUM_ID,
+            Guest::Aggregate => AGGREGATE_ID,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Binary,
+    Json,
+}
+
+impl Format {
+    /// JSON for paths ending in `.json`, binary otherwise.
+    pub fn for_path(path: &Path) -> Format {
+        match path.extension() {
+            Some(ext) if ext == "json" => Format::Json,
+            _ => Format::Binary,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReceiptFile {
+    /// Must stay the first field, whatever else changes: `from_bytes`
+    /// reads it on its own before the rest.
+    pub format_version: u32,
+    pub guest: Guest,
+    /// The image that the receipt claims to be from. The verifier compares
+    /// it with the image it trusts before checking the seal.
+    pub image_id: [u32; 8],
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// The version of the host that wrote the file.
+    pub host_version: String,
+    pub receipt: Receipt,
+}
+
+/// The start of every version of `ReceiptFile`.
+#[derive(Deserialize)]
+struct Header {
+    format_version: u32,
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(io::Error),
+    Malformed(String),
+    Version(u32),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(e) => write!(f, "{}", e),
+            FileError::Malformed(e) => write!(f, "not a receipt file: {}", e),
+            FileError::Version(v) => {
+                write!(f, "receipt file version {}, expected {}", v, FORMAT_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<io::Error> for FileError {
+    fn from(e: io::Error) -> Self {
+        FileError::Io(e)
+    }
+}
+
+/// The image id as risc0 prints it: the hex of the words in little endian.
+pub fn image_hex(image_id: &[u32; 8]) -> String {
+    let bytes: Vec<u8> = image_id
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+    hex(&bytes)
+}
+
+/// The inverse of `image_hex`.
+pub fn parse_image_id(text: &str) -> Result<[u32; 8], String> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 64 || !bytes.iter().all(u8::is_ascii_hexdigit) {
+        return Err("expected 64 hex digits".to_string());
+    }
+    let mut out = [0u32; 8];
+    for (i, word) in out.iter_mut().enumerate() {
+        let mut le = [0u8; 4];
+        for (j, byte) in le.iter_mut().enumerate() {
+            let at = 8 * i + 2 * j;
+            *byte = u8::from_str_radix(&text[at..at + 2], 16).unwrap();
+        }
+        *word = u32::from_le_bytes(le);
+    }
+    Ok(out)
+}
+
+/// Bincode after `MAGIC`, JSON otherwise. Bincode stops after the fields of
+/// `T` and JSON skips unknown fields, which is what lets `Header` be read on
+/// its own.
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, FileError> {
+    match data.strip_prefix(MAGIC) {
+        Some(rest) => bincode::deserialize(rest).map_err(|e| FileError::Malformed(e.to_string())),
+        None => serde_json::from_slice(data).map_err(|e| FileError::Malformed(e.to_string())),
+    }
+}
+
+impl ReceiptFile {
+    pub fn new(guest: Guest, receipt: Receipt) -> Self {
+        ReceiptFile {
+            format_version: FORMAT_VERSION,
+            guest,
+            image_id: guest.image_id(),
+            created: crate::now(),
+            host_version: env!("CARGO_PKG_VERSION").to_string(),
+            receipt,
+        }
+    }
+
+    pub fn to_bytes(&self, format: Format) -> Vec<u8> {
+        match format {
+            Format::Json => serde_json::to_vec_pretty(self).unwrap(),
+            Format::Binary => {
+                let mut data = MAGIC.to_vec();
+                bincode::serialize_into(&mut data, self).unwrap();
+                data
+            }
+        }
+    }
+
+    /// The version is checked before the rest is decoded, so that a file
+    /// of another layout is reported as such and not as malformed.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, FileError> {
+        let header: Header = decode(data)?;
+        if header.format_version != FORMAT_VERSION {
+            return Err(FileError::Version(header.format_version));
+        }
+        decode(data)
+    }
+
+    pub fn save(&self, path: &Path, format: Format) -> Result<(), FileError> {
+        std::fs::write(path, self.to_bytes(format))?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, FileError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_zkvm::sha::Digest;
+    use risc0_zkvm::{ExitCode, InnerReceipt, MaybePruned, ReceiptClaim};
+
+    fn file() -> ReceiptFile {
+        let claim = ReceiptClaim {
+            pre: MaybePruned::Pruned(Digest::ZERO),
+            post: MaybePruned::Pruned(Digest::ZERO),
+            exit_code: ExitCode::Halted(0),
+            input: Digest::ZERO,
+            output: MaybePruned::Pruned(Digest::ZERO),
+        };
+        let receipt = Receipt::new(InnerReceipt::Fake { claim }, b"journal".to_vec());
+        ReceiptFile::new(Guest::Sum, receipt)
+    }
+
+    fn assert_round_trip(format: Format) {
+        let file = file();
+        let read = ReceiptFile::from_bytes(&file.to_bytes(format)).unwrap();
+        assert_eq!(read.format_version, FORMAT_VERSION);
+        assert_eq!(read.guest, file.guest);
+        assert_eq!(read.image_id, file.image_id);
+        assert_eq!(read.created, file.created);
+        assert_eq!(read.host_version, file.host_version);
+        assert_eq!(read.receipt.journal.bytes, file.receipt.journal.bytes);
+        assert_eq!(read.to_bytes(format), file.to_bytes(format));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        assert_round_trip(Format::Binary);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_round_trip(Format::Json);
+    }
+
+    #[test]
+    fn other_versions_are_rejected_before_the_body() {
+        let mut binary = MAGIC.to_vec();
+        binary.extend_from_slice(&99u32.to_le_bytes());
+        binary.extend_from_slice(b"not a receipt");
+        assert!(matches!(
+            ReceiptFile::from_bytes(&binary),
+            Err(FileError::Version(99))
+        ));
+
+        let json = br#"{"format_version": 99, "receipt": "not a receipt"}"#;
+        assert!(matches!(
+            ReceiptFile::from_bytes(json),
+            Err(FileError::Version(99))
+        ));
+    }
+
+    #[test]
+    fn bad_magic_is_malformed() {
+        let mut data = file().to_bytes(Format::Binary);
+        data[0] = b'X';
+        assert!(matches!(
+            ReceiptFile::from_bytes(&data),
+            Err(FileError::Malformed(_))
+        ));
+        assert!(matches!(
+            ReceiptFile::from_bytes(b""),
+            Err(FileError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn image_id_hex_round_trip() {
+        let image_id = Guest::Aggregate.image_id();
+        assert_eq!(parse_image_id(&image_hex(&image_id)), Ok(image_id));
+        assert!(parse_image_id(&image_hex(&image_id)[1..]).is_err());
+    }
+}
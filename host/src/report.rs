@@ -0,0 +1,243 @@
+// Printing a journal, either as text for people or as JSON for scripts.
+
+use crate::hex;
+use crate::receipt::image_hex;
+use fps_types::aggregate::AggregateOutput;
+use fps_types::amount::AmountOutput;
+use fps_types::cycles::CycleReport;
+use fps_types::disclosure::Disclosed;
+use fps_types::sum::SumOutput;
+use fps_types::PublicOutput;
+use serde_json::{json, Value};
+
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).into_owned()
+}
+
+fn print_disclosed(label: &str, disclosed: &Disclosed) {
+    match disclosed {
+        Disclosed::Hidden => println!("{}: hidden", label),
+        Disclosed::Revealed(value) => println!("{}: {}", label, text(value)),
+        Disclosed::Committed(hash) => println!("{}: committed {}", label, hex(hash)),
+    }
+}
+
+pub fn print_output(output: &PublicOutput) {
+    println!(
+        "template: {} ({})",
+        output.template_id,
+        hex(&output.template_hash)
+    );
+    println!(
+        "dkim: d={} s={} key={}",
+        text(&output.dkim_domain),
+        text(&output.dkim_selector),
+        hex(&output.key_hash)
+    );
+    let currency = text(&output.currency);
+    match output.amount {
+        AmountOutput::Revealed(amount) => {
+            println!("amount: {} {}.{:02}", currency, amount / 100, amount % 100)
+        }
+        AmountOutput::Hidden => println!("amount: {} hidden", currency),
+        AmountOutput::Committed(hash) => println!("amount: {} committed {}", currency, hex(&hash)),
+        AmountOutput::Predicate { predicate, holds } => {
+            println!("amount: {} {:?} holds={}", currency, predicate, holds)
+        }
+    }
+    print_disclosed("name", &output.name);
+    print_disclosed("email", &output.email);
+    if let Some(hash) = output.full_name_hash {
+        println!("full name: {}", hex(&hash));
+    }
+    if let Some(hash) = output.full_email_hash {
+        println!("full email: {}", hex(&hash));
+    }
+    print_disclosed("receiver", &output.receiver);
+    if let Some(hash) = output.receiver_hash {
+        println!("receiver address: {}", hex(&hash));
+    }
+    match output.timestamp {
+        Disclosed::Hidden => println!("timestamp: hidden"),
+        Disclosed::Revealed(timestamp) => println!("timestamp: {}", timestamp),
+        Disclosed::Committed(hash) => println!("timestamp: committed {}", hex(&hash)),
+    }
+    println!(
+        "signed within: {:?}..={:?}",
+        output.freshness.not_before, output.freshness.not_after
+    );
+    print_disclosed("receipt number", &output.receipt_number);
+    print_disclosed("message id", &output.message_id);
+    print_disclosed("comment line", &output.comment_line);
+    println!("policy: {}", hex(&output.policy_hash));
+    println!(
+        "nullifier: {} (app id {:?})",
+        hex(&output.nullifier),
+        text(&output.app_id)
+    );
+    let context = &output.context;
+    println!(
+        "context: order {:?} nonce {} chain {:?} recipient {}",
+        text(&context.order_id),
+        hex(&context.nonce),
+        context.chain_id,
+        hex(&context.recipient)
+    );
+}
+
+/// `null` when hidden, otherwise `{"revealed": text}` or `{"committed": hex}`.
+fn disclosed_json(disclosed: &Disclosed) -> Value {
+    match disclosed {
+        Disclosed::Hidden => Value::Null,
+        Disclosed::Revealed(value) => json!({ "revealed": text(value) }),
+        Disclosed::Committed(hash) => json!({ "committed": hex(hash) }),
+    }
+}
+
+fn amount_json(amount: &AmountOutput) -> Value {
+    match *amount {
+        AmountOutput::Revealed(amount) => json!({ "revealed": amount }),
+        AmountOutput::Hidden => Value::Null,
+        AmountOutput::Committed(hash) => json!({ "committed": hex(&hash) }),
+        AmountOutput::Predicate { predicate, holds } => {
+            json!({ "predicate": predicate, "holds": holds })
+        }
+    }
+}
+
+/// The journal with text fields as strings and hashes in hex.
+pub fn output_json(output: &PublicOutput) -> Value {
+    let context = &output.context;
+    json!({
+        "version": output.version,
+        "template_id": output.template_id,
+        "template_hash": hex(&output.template_hash),
+        "dkim_domain": text(&output.dkim_domain),
+        "dkim_selector": text(&output.dkim_selector),
+        "key_hash": hex(&output.key_hash),
+        "currency": text(&output.currency),
+        "amount": amount_json(&output.amount),
+        "name": disclosed_json(&output.name),
+        "email": disclosed_json(&output.email),
+        "full_name_hash": output.full_name_hash.map(|h| hex(&h)),
+        "full_email_hash": output.full_email_hash.map(|h| hex(&h)),
+        "receiver": disclosed_json(&output.receiver),
+        "receiver_hash": output.receiver_hash.map(|h| hex(&h)),
+        "timestamp": match output.timestamp {
+            Disclosed::Hidden => Value::Null,
+            Disclosed::Revealed(timestamp) => json!({ "revealed": timestamp }),
+            Disclosed::Committed(hash) => json!({ "committed": hex(&hash) }),
+        },
+        "freshness": output.freshness,
+        "receipt_number": disclosed_json(&output.receipt_number),
+        "message_id": disclosed_json(&output.message_id),
+        "comment_line": disclosed_json(&output.comment_line),
+        "policy_hash": hex(&output.policy_hash),
+        "app_id": text(&output.app_id),
+        "nullifier": hex(&output.nullifier),
+        "context": {
+            "order_id": text(&context.order_id),
+            "nonce": hex(&context.nonce),
+            "chain_id": context.chain_id,
+            "recipient": hex(&context.recipient),
+        },
+    })
+}
+
+pub fn print_sum(output: &SumOutput) {
+    println!(
+        "{} receipts to {}: {} {}.{:02}",
+        output.count,
+        text(&output.payee),
+        text(&output.currency),
+        output.total / 100,
+        output.total % 100
+    );
+    println!(
+        "signed within: {:?}..={:?}",
+        output.period.not_before, output.period.not_after
+    );
+    println!("key: {}", hex(&output.key_hash));
+    println!("set: {}", hex(&output.set_commitment));
+}
+
+pub fn sum_json(output: &SumOutput) -> Value {
+    json!({
+        "version": output.version,
+        "key_hash": hex(&output.key_hash),
+        "currency": text(&output.currency),
+        "payee": text(&output.payee),
+        "period": output.period,
+        "app_id": text(&output.app_id),
+        "count": output.count,
+        "total": output.total,
+        "set_commitment": hex(&output.set_commitment),
+    })
+}
+
+pub fn print_aggregate(output: &AggregateOutput) {
+    println!(
+        "{} receipts to {}: {} {:?}",
+        output.count,
+        text(&output.payee),
+        text(&output.currency),
+        output.total
+    );
+    println!(
+        "signed within: {:?}..={:?}",
+        output.period.not_before, output.period.not_after
+    );
+    println!("receipts of image: {}", image_hex(&output.image_id));
+    println!("key: {}", hex(&output.key_hash));
+    println!("set: {}", hex(&output.set_commitment));
+}
+
+pub fn aggregate_json(output: &AggregateOutput) -> Value {
+    json!({
+        "version": output.version,
+        "image_id": image_hex(&output.image_id),
+        "key_hash": hex(&output.key_hash),
+        "currency": text(&output.currency),
+        "app_id": text(&output.app_id),
+        "payee": text(&output.payee),
+        "period": output.period,
+        "count": output.count,
+        "total": amount_json(&output.total),
+        "set_commitment": hex(&output.set_commitment),
+    })
+}
+
+/// One line per phase, with its share of the total.
+pub fn print_cycles(cycles: &CycleReport) {
+    let mut phases = vec![
+        ("deserialize".to_string(), cycles.deserialize),
+        ("verify".to_string(), cycles.verify),
+        ("validate".to_string(), cycles.validate),
+        ("body".to_string(), cycles.body),
+        ("body hash".to_string(), cycles.body_hash),
+        ("header".to_string(), cycles.header),
+        ("header hash".to_string(), cycles.header_hash),
+        ("base64".to_string(), cycles.base64),
+        ("parse".to_string(), cycles.parse),
+        ("signature".to_string(), cycles.signature),
+    ];
+    for (i, count) in cycles.squarings.iter().enumerate() {
+        phases.push((format!("squaring {}", i + 1), *count));
+    }
+    phases.extend([
+        ("multiply".to_string(), cycles.multiply),
+        ("reduce".to_string(), cycles.reduce),
+        ("compare".to_string(), cycles.compare),
+        ("commit".to_string(), cycles.commit),
+    ]);
+    let total = cycles.total.max(1) as f64;
+    for (phase, count) in phases {
+        println!(
+            "{:>12}: {:>10} {:>5.1}%",
+            phase,
+            count,
+            100.0 * count as f64 / total
+        );
+    }
+    println!("{:>12}: {:>10}", "total", cycles.total);
+}
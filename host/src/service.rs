@@ -0,0 +1,321 @@
+// A local HTTP service that proves receipts in the background.
+//
+//   POST /jobs                   queue a job, from JSON or a raw .eml
+//   GET  /jobs/<id>              its status, and the journal once done
+//   GET  /jobs/<id>/receipt      the receipt file; ?format=json for JSON
+//
+// The JSON of `POST /jobs` has either `eml`, the raw email as a string, or
+// `witness`, as written by `extract`, and optionally `params` and
+// `secrets`. A body of type message/rfc822 is an email proven with the
+// default params. Requests are answered on the calling thread; the proving
+// happens on a pool of workers that take jobs from the `JobStore`.
+//
+// Job ids are sequential, so anyone who can reach the service can read the
+// status of every job. The salts that open the committed fields are
+// therefore only in the answer to `POST /jobs`, and never in the status.
+
+use crate::jobs::{Job, JobStore, Status};
+use crate::receipt::{Format, Guest, ReceiptFile};
+use crate::{report, witness, Failure};
+use fps_types::{ProofParams, PublicOutput, Secrets, Witness};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Larger bodies are refused; the emails are a few kilobytes.
+const MAX_BODY: u64 = 1 << 20;
+
+/// How often an idle worker looks for jobs that it was not woken for.
+const POLL: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct Submit {
+    eml: Option<String>,
+    witness: Option<Witness>,
+    #[serde(default)]
+    params: ProofParams,
+    secrets: Option<Secrets>,
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, value: Value) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: impl fmt::Display) -> Reply {
+        Reply::json(status, json!({ "error": message.to_string() }))
+    }
+}
+
+/// Wakes an idle worker when a job is queued.
+///
+/// Rings are counted, so that one made between a worker's empty claim and
+/// its wait is not lost.
+#[derive(Default)]
+struct Bell {
+    pending: Mutex<usize>,
+    ring: Condvar,
+}
+
+impl Bell {
+    fn ring(&self) {
+        *self.pending.lock().unwrap() += 1;
+        self.ring.notify_all();
+    }
+
+    fn wait(&self) {
+        let pending = self.pending.lock().unwrap();
+        let (mut pending, _) = self
+            .ring
+            .wait_timeout_while(pending, POLL, |pending| *pending == 0)
+            .unwrap();
+        *pending = pending.saturating_sub(1);
+    }
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+/// Whether the body is a raw email, whatever the parameters and case of the
+/// media type.
+fn is_email(request: &Request) -> bool {
+    header(request, "Content-Type").is_some_and(|value| {
+        let media_type = value.split(';').next().unwrap_or("");
+        media_type.trim().eq_ignore_ascii_case("message/rfc822")
+    })
+}
+
+fn from_eml(raw: &[u8]) -> Result<Witness, String> {
+    witness::from_eml(raw)
+        .map(|extracted| extracted.witness)
+        .map_err(|e| e.to_string())
+}
+
+/// The witness, params and secrets of a job, checked as far as the host
+/// can so that bad inputs are refused rather than queued.
+fn inputs(request: &Request, body: &[u8]) -> Result<(Witness, ProofParams, Secrets), String> {
+    // A raw email is used as is: any byte replaced on the way would change
+    // the body hash.
+    let (witness, params, secrets) = if is_email(request) {
+        (from_eml(body)?, ProofParams::default(), None)
+    } else {
+        let submit: Submit = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+        let witness = match (submit.eml, submit.witness) {
+            (Some(eml), None) => from_eml(eml.as_bytes())?,
+            (None, Some(witness)) => witness,
+            _ => return Err("expected exactly one of eml and witness".to_string()),
+        };
+        (witness, submit.params, submit.secrets)
+    };
+    let secrets = secrets.unwrap_or_else(crate::random_secrets);
+    crate::check_inputs(&witness, &secrets).map_err(|e| e.message)?;
+    Ok((witness, params, secrets))
+}
+
+fn submit(store: &JobStore, bell: &Bell, request: &mut Request) -> Reply {
+    let mut body = Vec::new();
+    if let Err(e) = request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body)
+    {
+        return Reply::error(400, e);
+    }
+    if body.len() as u64 > MAX_BODY {
+        return Reply::error(413, "the body is too large");
+    }
+    let (witness, params, secrets) = match inputs(request, &body) {
+        Ok(inputs) => inputs,
+        Err(e) => return Reply::error(400, e),
+    };
+    match store.enqueue(&witness, &params, &secrets) {
+        Ok(id) => {
+            bell.ring();
+            let mut value = json!({ "id": id, "status": Status::Queued.as_str() });
+            let salts = crate::salts(&params, &secrets);
+            if !salts.is_empty() {
+                value["salts"] = crate::salts_json(&salts);
+            }
+            Reply::json(202, value)
+        }
+        Err(e) => Reply::error(500, e),
+    }
+}
+
+fn status_json(job: &Job) -> Value {
+    let mut value = json!({
+        "id": job.id,
+        "status": job.status.as_str(),
+        "created": job.created,
+        "started": job.started,
+        "finished": job.finished,
+        "error": job.error,
+    });
+    if let Some(journal) = &job.journal {
+        let journal = risc0_zkvm::Journal::new(journal.clone());
+        match crate::decode::<PublicOutput>(&journal) {
+            Ok(output) => value["journal"] = report::output_json(&output),
+            Err(e) => value["error"] = Value::from(e.message),
+        }
+    }
+    value
+}
+
+fn receipt(store: &JobStore, id: i64, query: &str) -> Reply {
+    let data = match store.receipt(id) {
+        Ok(Some(data)) => data,
+        Ok(None) => match store.get(id) {
+            Ok(Some(job)) => {
+                return Reply::error(409, format!("the job is {}", job.status.as_str()))
+            }
+            Ok(None) => return Reply::error(404, "no such job"),
+            Err(e) => return Reply::error(500, e),
+        },
+        Err(e) => return Reply::error(500, e),
+    };
+    if query.split('&').any(|param| param == "format=json") {
+        match ReceiptFile::from_bytes(&data) {
+            Ok(file) => Reply {
+                status: 200,
+                content_type: "application/json",
+                body: file.to_bytes(Format::Json),
+            },
+            Err(e) => Reply::error(500, e),
+        }
+    } else {
+        Reply {
+            status: 200,
+            content_type: "application/octet-stream",
+            body: data,
+        }
+    }
+}
+
+fn status(store: &JobStore, id: i64) -> Reply {
+    match store.get(id) {
+        Ok(Some(job)) => Reply::json(200, status_json(&job)),
+        Ok(None) => Reply::error(404, "no such job"),
+        Err(e) => Reply::error(500, e),
+    }
+}
+
+fn route(store: &JobStore, bell: &Bell, request: &mut Request) -> Reply {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let method = request.method().clone();
+
+    match (method, segments.as_slice()) {
+        (Method::Post, ["jobs"]) => submit(store, bell, request),
+        (Method::Get, ["jobs", id]) => match id.parse() {
+            Ok(id) => status(store, id),
+            Err(_) => Reply::error(404, "no such job"),
+        },
+        (Method::Get, ["jobs", id, "receipt"]) => match id.parse() {
+            Ok(id) => receipt(store, id, query),
+            Err(_) => Reply::error(404, "no such job"),
+        },
+        (_, ["jobs"] | ["jobs", _] | ["jobs", _, "receipt"]) => {
+            Reply::error(405, "method not allowed")
+        }
+        _ => Reply::error(404, "not found"),
+    }
+}
+
+/// Prove one claimed job. A panic in the prover fails the job rather than
+/// the worker.
+fn run(store: &JobStore, job: &Job) {
+    let proven = panic::catch_unwind(AssertUnwindSafe(|| {
+        crate::prove_journal(&job.witness, &job.params, &job.secrets, None)
+    }))
+    .unwrap_or_else(|_| Err(Failure::rejected("the prover panicked")));
+
+    let done = match proven {
+        Ok((receipt, _)) => {
+            let journal = receipt.journal.bytes.clone();
+            let file = ReceiptFile::new(Guest::Method, receipt);
+            eprintln!("job {}: done", job.id);
+            store.finish(job.id, &journal, &file.to_bytes(Format::Binary))
+        }
+        Err(failure) => {
+            eprintln!("job {}: {}", job.id, failure.message);
+            store.fail(job.id, &failure.message)
+        }
+    };
+    if let Err(e) = done {
+        eprintln!("job {}: {}", job.id, e);
+    }
+}
+
+fn work(db: PathBuf, bell: Arc<Bell>) {
+    let mut store = match JobStore::open(&db) {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("worker: {}: {}", db.display(), e);
+            return;
+        }
+    };
+    loop {
+        match store.claim() {
+            Ok(Some(job)) => run(&store, &job),
+            Ok(None) => bell.wait(),
+            Err(e) => {
+                eprintln!("worker: {}", e);
+                bell.wait();
+            }
+        }
+    }
+}
+
+pub fn serve(addr: &str, db: &Path, workers: usize) -> Result<(), Failure> {
+    let store =
+        JobStore::open(db).map_err(|e| Failure::input(format!("{}: {}", db.display(), e)))?;
+    let requeued = store
+        .requeue_running()
+        .map_err(|e| Failure::input(format!("{}: {}", db.display(), e)))?;
+    if requeued > 0 {
+        eprintln!("requeued {} interrupted jobs", requeued);
+    }
+
+    let bell = Arc::new(Bell::default());
+    for _ in 0..workers.max(1) {
+        let db = db.to_path_buf();
+        let bell = bell.clone();
+        std::thread::spawn(move || work(db, bell));
+    }
+
+    let server = Server::http(addr).map_err(|e| Failure::input(format!("{}: {}", addr, e)))?;
+    eprintln!("listening on {}", addr);
+    for mut request in server.incoming_requests() {
+        let reply = route(&store, &bell, &mut request);
+        let content_type = Header::from_bytes(&b"Content-Type"[..], reply.content_type).unwrap();
+        let response = Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(content_type);
+        if let Err(e) = request.respond(response) {
+            eprintln!("respond: {}", e);
+        }
+    }
+    Ok(())
+}
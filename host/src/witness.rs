@@ -0,0 +1,208 @@
+// Building the witness of the guest from an email that matched a template.
+
+use crate::detect::{self, Bindings};
+use crate::eml::{Email, EmlError};
+use base64::Engine;
+use core::str::FromStr;
+use fps_types::template::{Field, Fields, Template};
+use fps_types::{validate, Witness};
+use num_bigint_dig::BigUint;
+use std::fmt;
+
+/// The bank's RSA-2048 modulus, the same as `rsa::N` in the guest.
+const MODULUS: &str = "22181287481343866536926164726351287326530456851865740940302258624292918842046294265777588938243700158420966504059481663514441470940350196901315671547076005234970874435909476092497483551273288093189364709035514616037071211153823131905024178182878201024915500433097297265826798822817484748700216324125712309789054401424099125210527384783630725436400275931057214172116786047287671841780210364049070913138670556222022084829676330760494242212963241225957072902927387309610872757297833214507573774777580968710434530894604337230857277368168283766335313014325255932691808839056156851505239358105335763858378332776753927248103";
+
+/// The signature times R = 2^2048 mod n, in little endian: the Montgomery
+/// form that the guest exponentiates.
+pub fn signature_mont(signature: &[u8]) -> Vec<u8> {
+    let n = BigUint::from_str(MODULUS).unwrap();
+    let r = (BigUint::from(1u32) << 2048) % &n;
+    let mut out = (BigUint::from_bytes_be(signature) * r % n).to_bytes_le();
+    out.resize(256, 0);
+    out
+}
+
+/// The witness for the fields extracted by `detect`, and the `b=` value of
+/// the DKIM signature.
+pub fn from_bindings(template_id: u32, bindings: &Bindings, signature: &[u8]) -> Witness {
+    let field = |field: Field| bindings.field(field).to_vec();
+    Witness {
+        template_id,
+        comment_line: field(Field::CommentLine),
+        amount: field(Field::Amount),
+        name: field(Field::Name),
+        email: field(Field::Email),
+        date_body: field(Field::DateBody),
+        date_head: field(Field::DateHead),
+        receiver: field(Field::Receiver),
+        message_id: field(Field::MessageId),
+        dkim_timestamp: field(Field::DkimTimestamp),
+        bh_base64: field(Field::BhBase64),
+        receipt_number: field(Field::ReceiptNumber),
+        signature_mont: signature_mont(signature),
+        extra_parts: bindings.extra_parts().to_vec(),
+    }
+}
+
+#[derive(Debug)]
+pub enum ExtractError {
+    Eml(EmlError),
+    /// No template fits: the id of the closest one, and the diff against it.
+    NoTemplate(u32, String),
+    Shape(Field),
+    Signature(String),
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::Eml(e) => write!(f, "{}", e),
+            ExtractError::NoTemplate(id, _) => {
+                write!(f, "no known template fits, the closest is {}", id)
+            }
+            ExtractError::Shape(field) => {
+                write!(f, "{:?} does not have the shape the guest accepts", field)
+            }
+            ExtractError::Signature(e) => write!(f, "b=: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl From<EmlError> for ExtractError {
+    fn from(e: EmlError) -> Self {
+        ExtractError::Eml(e)
+    }
+}
+
+/// An email matched against the templates.
+pub struct Extracted {
+    pub template: &'static Template,
+    pub bindings: Bindings,
+    pub witness: Witness,
+}
+
+/// Parse a raw .eml, find the template that it fits and build the witness.
+pub fn from_eml(raw: &[u8]) -> Result<Extracted, ExtractError> {
+    let canonical = Email::parse(raw)?.canonicalize()?;
+
+    let Some((template, bindings)) = detect::detect(&canonical) else {
+        let (template, report) = detect::closest(&canonical);
+        return Err(ExtractError::NoTemplate(template.id, report));
+    };
+    validate::check(&bindings).map_err(ExtractError::Shape)?;
+
+    let signature = canonical
+        .dkim
+        .tag(b"b")
+        .ok_or_else(|| ExtractError::Signature("missing".to_string()))?;
+    let signature = base64::engine::general_purpose::STANDARD
+        .decode(signature)
+        .map_err(|e| ExtractError::Signature(e.to_string()))?;
+    let witness = from_bindings(template.id, &bindings, &signature);
+
+    Ok(Extracted {
+        template,
+        bindings,
+        witness,
+    })
+}
+
+/// The `b=` of the sample receipt, as a decimal number.
+const SAMPLE_SIGNATURE: &str = "10625512586255484529905913055518690041367016184139603208541053043067627026596170923852906588557400770118440711602759466843726969481836140444332240137316710742516639602143210812428949677883618101389359047511373992985514651735606880700898915238753116119729027152984350103669187052930077764847488756122702904657364560056547986875486100784117240746834017001781152776378161718685359034677365279732516706989700441393316707925352884322083934071736978776879652906169104284627863089773539963210536556881828553404441522346601372075574798832939215784948057194166388978217945842562118998420736255452138960283685002443406516028414";
+
+/// The receipt that the repository was developed against.
+pub fn sample() -> Witness {
+    let comment_line = b"2772651_343290592.1700310140503";
+    let amount = b"10.00";
+    let name = b"CHEN W******";
+    let email = b"w********@chenweikeng.com";
+    let date_body = b"18/11/2023";
+    let date_head = b"Sat, 18 Nov 2023 20:22:20 +0800";
+    let receiver = b"\"WEIKENG@CHENWEIKENG.COM\" <WEIKENG@CHENWEIKENG.COM>";
+    let message_id = b"<101958940.2772652.1700310140503.JavaMail.1000830000@hk-boa-15-5f8fl>";
+    let dkim_timestamp = b"1700310181";
+    let bh_base64 = b"hJ/+UNkf1BHOUMaYhrzDzD3adraujFmKjZajNWOLYT4=";
+    let receipt_number = b"2311-182022218700";
+
+    let signature = BigUint::from_str(SAMPLE_SIGNATURE).unwrap();
+
+    let signature_mont = signature_mont(&signature.to_bytes_be());
+
+    Witness {
+        template_id: 1,
+        comment_line: comment_line.to_vec(),
+        amount: amount.to_vec(),
+        name: name.to_vec(),
+        email: email.to_vec(),
+        date_body: date_body.to_vec(),
+        date_head: date_head.to_vec(),
+        receiver: receiver.to_vec(),
+        message_id: message_id.to_vec(),
+        dkim_timestamp: dkim_timestamp.to_vec(),
+        bh_base64: bh_base64.to_vec(),
+        receipt_number: receipt_number.to_vec(),
+        signature_mont,
+        extra_parts: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use fps_types::template::SC_PAY_SEND_MONEY_V1;
+
+    /// The sample receipt as the bank sends it: folded headers, names in
+    /// their usual case and empty lines after the body.
+    pub fn sample_eml() -> Vec<u8> {
+        let witness = sample();
+        let signature = BigUint::from_str(SAMPLE_SIGNATURE).unwrap().to_bytes_be();
+        let b = base64::engine::general_purpose::STANDARD.encode(signature);
+        let text = |data: &[u8]| String::from_utf8(data.to_vec()).unwrap();
+        let mut raw = format!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=sc.com;\r\n\
+             \ts=k06k22gbledmsml; t={}; i=@sc.com;\r\n\
+             \tbh={};\r\n\
+             \th=Date:From:To:Message-ID:Subject:MIME-Version:Content-Type;\r\n\
+             \tb={}\r\n\
+             \t{}\r\n\
+             Date: {}\r\n\
+             From: Standard Chartered Alerts <OnlineBanking.HK@sc.com>\r\n\
+             To: {}\r\n\
+             Message-ID: {}\r\n\
+             Subject: =?UTF-8?Q?Send_Money_via_Standard_Chartered_?=\r\n \
+             =?UTF-8?Q?Pay_=E2=80=93_Receipt_No._{}?=\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; \r\n\
+             \tboundary=\"----=_Part_{}\"\r\n\
+             \r\n",
+            text(&witness.dkim_timestamp),
+            text(&witness.bh_base64),
+            &b[..172],
+            &b[172..],
+            text(&witness.date_head),
+            text(&witness.receiver),
+            text(&witness.message_id),
+            text(&witness.receipt_number),
+            text(&witness.comment_line),
+        )
+        .into_bytes();
+        raw.extend_from_slice(&SC_PAY_SEND_MONEY_V1.render_body(&witness));
+        raw.extend_from_slice(b"\r\n\r\n");
+        raw
+    }
+
+    #[test]
+    fn sample_eml_gives_the_sample_witness() {
+        let extracted = from_eml(&sample_eml()).unwrap();
+        assert_eq!(extracted.template.id, SC_PAY_SEND_MONEY_V1.id);
+        assert_eq!(extracted.witness, sample());
+    }
+
+    #[test]
+    fn bare_newlines_are_read_as_crlf() {
+        let raw: Vec<u8> = sample_eml().into_iter().filter(|&c| c != b'\r').collect();
+        assert_eq!(from_eml(&raw).unwrap().witness, sample());
+    }
+}
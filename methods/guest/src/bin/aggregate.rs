@@ -0,0 +1,80 @@
+// Aggregate of receipts proven separately by the main guest. Each journal
+// is verified as an assumption, then the same totals as in the sum guest
+// are committed.
+
+use fps_types::aggregate::{AggregateOutput, AggregateParams, AGGREGATE_VERSION};
+use fps_types::amount::AmountOutput;
+use fps_types::cycles::CycleReport;
+use fps_types::disclosure::Disclosed;
+use fps_types::sum;
+use fps_types::{PublicOutput, JOURNAL_VERSION};
+use method::Stopwatch;
+use risc0_zkvm::guest::env;
+use risc0_zkvm::serde;
+use sha2::{Digest, Sha256};
+
+risc0_zkvm::guest::entry!(main);
+
+fn main() {
+    let mut cycles = CycleReport::default();
+    let mut watch = Stopwatch::start();
+
+    let journals: Vec<Vec<u8>> = env::read();
+    let params: AggregateParams = env::read();
+    assert!(!journals.is_empty());
+    cycles.deserialize = watch.lap();
+
+    let mut first: Option<PublicOutput> = None;
+    let mut total = 0u64;
+    let mut nullifiers = Vec::with_capacity(journals.len());
+
+    for journal in &journals {
+        env::verify(params.image_id, journal).unwrap();
+        cycles.verify += watch.lap();
+
+        let output: PublicOutput = serde::from_slice(journal).unwrap();
+
+        assert_eq!(output.version, JOURNAL_VERSION);
+        assert_eq!(output.app_id, params.app_id);
+        assert_eq!(output.email, Disclosed::Revealed(params.payee.clone()));
+        let Disclosed::Revealed(timestamp) = output.timestamp else {
+            panic!("the time of a receipt is not revealed");
+        };
+        assert!(params.period.contains(timestamp));
+        let AmountOutput::Revealed(amount) = output.amount else {
+            panic!("the amount of a receipt is not revealed");
+        };
+        if let Some(first) = &first {
+            assert_eq!(output.key_hash, first.key_hash);
+            assert_eq!(output.currency, first.currency);
+        }
+
+        total = total.checked_add(amount).unwrap();
+        nullifiers.push(output.nullifier);
+        first.get_or_insert(output);
+        cycles.parse += watch.lap();
+    }
+
+    nullifiers.sort_unstable();
+    assert!(nullifiers.windows(2).all(|w| w[0] != w[1]));
+
+    let first = first.unwrap();
+    env::commit(&AggregateOutput {
+        version: AGGREGATE_VERSION,
+        image_id: params.image_id,
+        key_hash: first.key_hash,
+        currency: first.currency,
+        app_id: params.app_id,
+        payee: params.payee,
+        period: params.period,
+        count: nullifiers.len() as u32,
+        total: params
+            .total
+            .apply(total)
+            .expect("a total cannot be committed"),
+        set_commitment: Sha256::digest(&sum::set_preimage(&nullifiers)).into(),
+    });
+    cycles.commit = watch.lap();
+    cycles.total = env::get_cycle_count() as u64;
+    env::write(&cycles);
+}
@@ -0,0 +1,58 @@
+// Sum of many receipts to one payee. Each receipt is checked like in the
+// main guest, but only the count, the total and a commitment to the set
+// are committed.
+
+use fps_types::cycles::CycleReport;
+use fps_types::sum::{self, SumOutput, SumParams, SUM_VERSION};
+use fps_types::Witness;
+use method::{rsa, Stopwatch};
+use risc0_zkvm::guest::env;
+use sha2::{Digest, Sha256};
+
+risc0_zkvm::guest::entry!(main);
+
+fn main() {
+    let mut cycles = CycleReport::default();
+    let mut watch = Stopwatch::start();
+
+    let witnesses: Vec<Witness> = env::read();
+    let params: SumParams = env::read();
+    assert!(!witnesses.is_empty());
+    cycles.deserialize = watch.lap();
+
+    let mut currency = None;
+    let mut total = 0u64;
+    let mut nullifiers = Vec::with_capacity(witnesses.len());
+
+    for witness in &witnesses {
+        let checked = method::check(witness, &mut cycles);
+        assert_eq!(witness.email, params.payee);
+        assert!(params.period.contains(checked.timestamp));
+        assert_eq!(
+            *currency.get_or_insert(checked.template.currency),
+            checked.template.currency
+        );
+
+        total = total.checked_add(checked.amount).unwrap();
+        nullifiers.push(method::nullifier(&params.app_id, witness));
+    }
+
+    watch = Stopwatch::start();
+    nullifiers.sort_unstable();
+    assert!(nullifiers.windows(2).all(|w| w[0] != w[1]));
+
+    env::commit(&SumOutput {
+        version: SUM_VERSION,
+        key_hash: rsa::key_hash(),
+        currency: currency.unwrap().to_vec(),
+        payee: params.payee,
+        period: params.period,
+        app_id: params.app_id,
+        count: nullifiers.len() as u32,
+        total,
+        set_commitment: Sha256::digest(&sum::set_preimage(&nullifiers)).into(),
+    });
+    cycles.commit = watch.lap();
+    cycles.total = env::get_cycle_count() as u64;
+    env::write(&cycles);
+}
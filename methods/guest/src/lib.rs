@@ -0,0 +1,374 @@
+// The checks shared by the guest programs: that a witness renders to an
+// email signed by the bank, and what is derived from it.
+
+extern crate alloc;
+use base64ct::{Base64, Encoding};
+use core::mem::transmute;
+use fps_types::amount::{AmountDisclosure, AmountOutput};
+use fps_types::cycles::CycleReport;
+use fps_types::disclosure::{self, Disclosed, Disclosure};
+use fps_types::template::{self, Field, Template};
+use fps_types::{amount, date, nullifier, validate, Witness};
+use num_bigint::BigUint;
+use risc0_zkvm::guest::env;
+use sha2::{Digest, Sha256};
+
+pub mod dkim;
+
+pub mod rsa;
+
+/// Counts the cycles between laps, for the `CycleReport`.
+pub struct Stopwatch(usize);
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch(env::get_cycle_count())
+    }
+
+    /// The cycles since the start or the previous lap.
+    pub fn lap(&mut self) -> u64 {
+        let now = env::get_cycle_count();
+        let lap = now - self.0;
+        self.0 = now;
+        lap as u64
+    }
+}
+
+/// A receipt whose content checked out, and the values parsed from it.
+pub struct Checked {
+    pub template: &'static Template,
+    /// In minor units.
+    pub amount: u64,
+    /// Unix time of the `Date:` header.
+    pub timestamp: u64,
+    /// Unix time of the DKIM signature.
+    pub signed_at: u64,
+    /// The PKCS#1 v1.5 encoding of the signed hash, which the signature must
+    /// open to.
+    pub message: [u8; 255],
+}
+
+/// Check everything about the witness but the signature itself: the shape
+/// of the fields, the body hash, the amount and the dates.
+pub fn check_content(witness: &Witness, cycles: &mut CycleReport) -> Checked {
+    let mut watch = Stopwatch::start();
+    let template = template::by_id(witness.template_id).unwrap();
+    assert_eq!(validate::check(witness), Ok(()));
+    cycles.validate += watch.lap();
+
+    let body = template.render_body(witness);
+    assert!(template.check_delimiters(&body, witness));
+    cycles.body += watch.lap();
+
+    let body_hash = dkim::body_hash_sha256(&body);
+    cycles.body_hash += watch.lap();
+
+    let header = template.render_header(witness);
+    let original_header = template.render_dkim_header(witness);
+    cycles.header += watch.lap();
+
+    let data_hash = dkim::data_hash_sha256(&header, &original_header);
+    cycles.header_hash += watch.lap();
+
+    let mut dec_buf = [0u8; 32];
+    let decoded = Base64::decode(&witness.bh_base64, &mut dec_buf).unwrap();
+    assert_eq!(decoded, body_hash);
+    cycles.base64 += watch.lap();
+
+    let amount = amount::parse(&witness.amount).unwrap();
+    let (timestamp, signed_at) = date::check(
+        &witness.date_body,
+        &witness.date_head,
+        &witness.dkim_timestamp,
+    )
+    .unwrap();
+
+    assert!(template.check_parts(witness));
+
+    let mut msg_bytes = [0u8; 255];
+    msg_bytes[0] = 0x1;
+    for i in 1..203 {
+        msg_bytes[i] = 0xff;
+    }
+    msg_bytes[203] = 0x0;
+
+    msg_bytes[204] = 0x30;
+    msg_bytes[205] = 0x31;
+    msg_bytes[206] = 0x30;
+    msg_bytes[207] = 0x0d;
+    msg_bytes[208] = 0x06;
+    msg_bytes[209] = 0x09;
+    msg_bytes[210] = 0x60;
+    msg_bytes[211] = 0x86;
+    msg_bytes[212] = 0x48;
+    msg_bytes[213] = 0x01;
+    msg_bytes[214] = 0x65;
+    msg_bytes[215] = 0x03;
+    msg_bytes[216] = 0x04;
+    msg_bytes[217] = 0x02;
+    msg_bytes[218] = 0x01;
+    msg_bytes[219] = 0x05;
+    msg_bytes[220] = 0x00;
+    msg_bytes[221] = 0x04;
+    msg_bytes[222] = 0x20;
+
+    for i in 0..32 {
+        msg_bytes[223 + i] = data_hash[i];
+    }
+    cycles.parse += watch.lap();
+
+    Checked {
+        template,
+        amount,
+        timestamp,
+        signed_at,
+        message: msg_bytes,
+    }
+}
+
+/// Check that the signature, in Montgomery form, opens to `message` under
+/// the bank's key.
+pub fn check_signature(signature_mont: &[u8], message: &[u8; 255], cycles: &mut CycleReport) {
+    let mut watch = Stopwatch::start();
+    let sig_mont: BigUint = BigUint::from_bytes_le(signature_mont);
+
+    let mut sig_mont_limbs = [0u32; 64];
+    sig_mont_limbs.copy_from_slice(&sig_mont.to_u32_digits());
+
+    let mut cur_limbs = [0u32; 73];
+    let mut cur2_limbs = [0u32; 73];
+    cycles.signature += watch.lap();
+
+    // cur = ^2
+    rsa::montgomery_mul(&mut cur_limbs, &sig_mont_limbs, &sig_mont_limbs, false);
+    cycles.squarings[0] += watch.lap();
+    // cur2 = ^4
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[1] += watch.lap();
+    // cur = ^8
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[2] += watch.lap();
+    // cur2 = ^16
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[3] += watch.lap();
+    // cur = ^32
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[4] += watch.lap();
+    // cur2 = ^64
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[5] += watch.lap();
+    // cur = ^128
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[6] += watch.lap();
+    // cur2 = ^256
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[7] += watch.lap();
+    // cur = ^512
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[8] += watch.lap();
+    // cur2 = ^1024
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[9] += watch.lap();
+    // cur = ^2048
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[10] += watch.lap();
+    // cur2 = ^4096
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[11] += watch.lap();
+    // cur = ^8192
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[12] += watch.lap();
+    // cur2 = ^16384
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[13] += watch.lap();
+    // cur = ^32768
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[14] += watch.lap();
+    // cur2 = ^65536
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            false,
+        );
+    }
+    cycles.squarings[15] += watch.lap();
+    // cur = ^65537
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur2_limbs[0]),
+            &sig_mont_limbs,
+            false,
+        );
+    }
+    cycles.multiply += watch.lap();
+
+    let mut one = [0u32; 64];
+    one[0] = 1;
+
+    // cur removed montgomery
+    unsafe {
+        rsa::montgomery_mul(
+            &mut cur2_limbs,
+            transmute::<&u32, &[u32; 64]>(&cur_limbs[0]),
+            &one,
+            true,
+        );
+    }
+    cycles.reduce += watch.lap();
+    let msg = BigUint::from_slice(&cur2_limbs[0..64]);
+    let msg2 = BigUint::from_bytes_be(message);
+    assert_eq!(msg, msg2);
+    cycles.compare += watch.lap();
+}
+
+/// Check the witness, signature included.
+pub fn check(witness: &Witness, cycles: &mut CycleReport) -> Checked {
+    let checked = check_content(witness, cycles);
+    check_signature(&witness.signature_mont, &checked.message, cycles);
+    checked
+}
+
+pub fn nullifier(app_id: &[u8], witness: &Witness) -> [u8; 32] {
+    Sha256::digest(&nullifier::preimage(
+        app_id,
+        &witness.message_id,
+        &witness.receipt_number,
+    ))
+    .into()
+}
+
+/// The salted commitment to `value` as `field`.
+pub fn commit(field: Field, value: &[u8], seed: &[u8; 32]) -> [u8; 32] {
+    let salt = Sha256::digest(&disclosure::salt_preimage(seed, field)).into();
+    Sha256::digest(&disclosure::preimage(field, &salt, value)).into()
+}
+
+pub fn disclose(field: Field, disclosure: Disclosure, value: &[u8], seed: &[u8; 32]) -> Disclosed {
+    match disclosure {
+        Disclosure::Hide => Disclosed::Hidden,
+        Disclosure::Reveal => Disclosed::Revealed(value.to_vec()),
+        Disclosure::Commit => Disclosed::Committed(commit(field, value, seed)),
+    }
+}
+
+/// The time of the `Date:` header, committed as `Field::DateHead`.
+pub fn disclose_timestamp(
+    disclosure: Disclosure,
+    timestamp: u64,
+    seed: &[u8; 32],
+) -> Disclosed<u64> {
+    match disclosure {
+        Disclosure::Hide => Disclosed::Hidden,
+        Disclosure::Reveal => Disclosed::Revealed(timestamp),
+        Disclosure::Commit => {
+            Disclosed::Committed(commit(Field::DateHead, &timestamp.to_le_bytes(), seed))
+        }
+    }
+}
+
+pub fn disclose_amount(disclosure: AmountDisclosure, amount: u64, seed: &[u8; 32]) -> AmountOutput {
+    disclosure.apply(amount).unwrap_or_else(|| {
+        AmountOutput::Committed(commit(Field::Amount, &amount.to_le_bytes(), seed))
+    })
+}
@@ -0,0 +1,48 @@
+// Aggregation of receipts proven one by one with the main guest, whose
+// journals the `aggregate` guest verifies as assumptions.
+//
+// The guest checks the receipts against whatever image id the prover gives
+// it, and commits that id. An aggregate proves nothing about payments unless
+// the verifier also checks that `AggregateOutput::image_id` is the image of
+// the main guest it trusts, on top of the image id of the aggregate itself.
+
+use crate::amount::{AmountDisclosure, AmountOutput};
+use crate::date::FreshnessWindow;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+/// Read by the `aggregate` guest after the journals.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct AggregateParams {
+    /// The image that proved the receipts, i.e. `METHOD_ID`.
+    pub image_id: [u32; 8],
+    /// Every receipt must have this application id in its nullifier.
+    pub app_id: Vec<u8>,
+    /// Every receipt must reveal this payee email, as masked by the bank.
+    pub payee: Vec<u8>,
+    /// Range of the `Date:` header of every receipt, which must reveal its
+    /// time.
+    pub period: FreshnessWindow,
+    /// How the total appears in the journal; it has no salt, so it cannot be
+    /// committed. Every receipt must reveal its amount for it to be summed.
+    pub total: AmountDisclosure,
+}
+
+pub const AGGREGATE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AggregateOutput {
+    pub version: u32,
+    /// The image the receipts were verified against, as chosen by the
+    /// prover; see the note at the top.
+    pub image_id: [u32; 8],
+    pub key_hash: [u8; 32],
+    pub currency: Vec<u8>,
+    pub app_id: Vec<u8>,
+    pub payee: Vec<u8>,
+    pub period: FreshnessWindow,
+    pub count: u32,
+    pub total: AmountOutput,
+    /// As in `SumOutput`, over the nullifiers of the receipts.
+    pub set_commitment: [u8; 32],
+}
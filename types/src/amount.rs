@@ -0,0 +1,169 @@
+// Amounts as written in the receipts, e.g. `10.00` or `1,234.50`, and what
+// the journal may say about them.
+
+use serde::{Deserialize, Serialize};
+
+/// Parse an amount into minor units (cents).
+///
+/// The integer part is plain digits or groups of three digits separated by
+/// commas, with no leading zero unless it is `0` itself. There are always
+/// exactly two decimals. Anything else, including an amount that does not
+/// fit in a `u64`, is rejected.
+pub fn parse(amount: &[u8]) -> Option<u64> {
+    let dot = amount.iter().position(|&c| c == b'.')?;
+    let (int, frac) = (&amount[..dot], &amount[dot + 1..]);
+
+    if frac.len() != 2 || !frac.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    if int.is_empty() || (int[0] == b'0' && int.len() > 1) {
+        return None;
+    }
+
+    if int.contains(&b',') {
+        let mut groups = int.split(|&c| c == b',');
+        let first = groups.next()?;
+        if first.is_empty() || first.len() > 3 {
+            return None;
+        }
+        if groups.any(|g| g.len() != 3) {
+            return None;
+        }
+    }
+
+    let mut value = 0u64;
+    for &c in int.iter().chain(frac.iter()) {
+        match c {
+            b',' => continue,
+            b'0'..=b'9' => {
+                value = value.checked_mul(10)?.checked_add((c - b'0') as u64)?;
+            }
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+/// A condition on the amount, proven without revealing the amount itself.
+/// Bounds are in minor units and inclusive.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmountPredicate {
+    AtLeast(u64),
+    AtMost(u64),
+    Between(u64, u64),
+    Exactly(u64),
+}
+
+impl AmountPredicate {
+    pub fn holds(&self, amount: u64) -> bool {
+        match *self {
+            AmountPredicate::AtLeast(min) => amount >= min,
+            AmountPredicate::AtMost(max) => amount <= max,
+            AmountPredicate::Between(min, max) => min <= amount && amount <= max,
+            AmountPredicate::Exactly(value) => amount == value,
+        }
+    }
+}
+
+/// How the amount appears in the journal, chosen per proof.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AmountDisclosure {
+    #[default]
+    Reveal,
+    Hide,
+    /// A salted commitment to the amount in minor units, as eight
+    /// little-endian bytes, with the salt of `Field::Amount`.
+    Commit,
+    Predicate(AmountPredicate),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AmountOutput {
+    Revealed(u64),
+    Hidden,
+    Committed([u8; 32]),
+    /// The predicate and whether the amount satisfies it.
+    Predicate {
+        predicate: AmountPredicate,
+        holds: bool,
+    },
+}
+
+impl AmountDisclosure {
+    /// The output for `amount`, or `None` for `Commit`, whose hash needs a
+    /// salt and is computed by the guest.
+    pub fn apply(&self, amount: u64) -> Option<AmountOutput> {
+        match *self {
+            AmountDisclosure::Reveal => Some(AmountOutput::Revealed(amount)),
+            AmountDisclosure::Hide => Some(AmountOutput::Hidden),
+            AmountDisclosure::Commit => None,
+            AmountDisclosure::Predicate(predicate) => Some(AmountOutput::Predicate {
+                predicate,
+                holds: predicate.holds(amount),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_bank_formats() {
+        assert_eq!(parse(b"10.00"), Some(1000));
+        assert_eq!(parse(b"0.00"), Some(0));
+        assert_eq!(parse(b"0.05"), Some(5));
+        assert_eq!(parse(b"1,000.00"), Some(100_000));
+        assert_eq!(parse(b"12,345,678.90"), Some(1_234_567_890));
+        assert_eq!(parse(b"1000.00"), Some(100_000));
+    }
+
+    #[test]
+    fn rejects_leading_zeros() {
+        for amount in [&b"01.00"[..], b"00.00", b"0,000.00", b"007.50"] {
+            assert_eq!(parse(amount), None, "{:?}", amount);
+        }
+    }
+
+    #[test]
+    fn rejects_misplaced_commas() {
+        for amount in [
+            &b"1,00.00"[..],
+            b"1000,000.00",
+            b",100.00",
+            b"100,.00",
+            b"1,,000.00",
+            b"1,000.0,0",
+        ] {
+            assert_eq!(parse(amount), None, "{:?}", amount);
+        }
+    }
+
+    #[test]
+    fn rejects_anything_but_two_decimals() {
+        for amount in [
+            &b"10"[..],
+            b"10.",
+            b"10.0",
+            b"10.000",
+            b".50",
+            b"1.00.00",
+            b"1.0a",
+            b"-1.00",
+            b"+1.00",
+            b" 1.00",
+            b"",
+        ] {
+            assert_eq!(parse(amount), None, "{:?}", amount);
+        }
+    }
+
+    #[test]
+    fn rejects_amounts_that_overflow() {
+        assert_eq!(parse(b"184467440737095516.15"), Some(u64::MAX));
+        assert_eq!(parse(b"184,467,440,737,095,516.15"), Some(u64::MAX));
+        assert_eq!(parse(b"184467440737095516.16"), None);
+        assert_eq!(parse(b"999999999999999999999.99"), None);
+    }
+}
@@ -0,0 +1,45 @@
+// Where the cycles of the guest go.
+//
+// The guest counts the cycles of each phase of the check and writes the
+// report to the host on stdout after committing the journal. It is not part
+// of the journal and nothing in it is proven: it is only for profiling.
+
+use serde::{Deserialize, Serialize};
+
+/// Cycles per phase, in the order the guest runs them. The guests that
+/// check several receipts add up the phases over all of them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct CycleReport {
+    /// Reading the inputs.
+    pub deserialize: u64,
+    /// Verifying the journals of other receipts as assumptions, in the
+    /// aggregate guest.
+    pub verify: u64,
+    /// Looking up the template and matching the fields to their patterns.
+    pub validate: u64,
+    /// Rendering the body and checking its MIME delimiters.
+    pub body: u64,
+    pub body_hash: u64,
+    /// Rendering the signed headers and the DKIM-Signature header.
+    pub header: u64,
+    pub header_hash: u64,
+    /// Decoding `bh=` and comparing it with the body hash.
+    pub base64: u64,
+    /// The amount, the dates, the extra parts and the PKCS#1 encoding, or
+    /// in the aggregate guest decoding and checking the journals.
+    pub parse: u64,
+    /// Loading the signature into limbs.
+    pub signature: u64,
+    /// The sixteen squarings of sig^65537, in order.
+    pub squarings: [u64; 16],
+    /// The final multiplication by the signature.
+    pub multiply: u64,
+    /// Converting out of Montgomery form.
+    pub reduce: u64,
+    /// Comparing with the expected encoding.
+    pub compare: u64,
+    /// The nullifier, the disclosures and committing the journal.
+    pub commit: u64,
+    /// The cycle count when the report is written.
+    pub total: u64,
+}
@@ -0,0 +1,259 @@
+// The three dates of a receipt: the `dd/mm/yyyy` date in the body, the
+// RFC 2822 `Date:` header, and the unix time in the DKIM `t=` tag.
+
+use serde::{Deserialize, Serialize};
+
+/// How long after the `Date:` header the bank may sign the email.
+pub const MAX_SIGNING_DELAY: u64 = 3600;
+
+/// How far the `Date:` header may be ahead of the signing time.
+pub const MAX_CLOCK_SKEW: u64 = 300;
+
+const MONTHS: [&[u8; 3]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+const WEEKDAYS: [&[u8; 3]; 7] = [b"Sun", b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat"];
+
+fn is_leap(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn digits(data: &[u8]) -> Option<u64> {
+    if data.is_empty() || data.len() > 19 || !data.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(
+        data.iter()
+            .fold(0u64, |acc, &c| acc * 10 + (c - b'0') as u64),
+    )
+}
+
+fn civil(year: u64, month: u64, day: u64) -> Option<i64> {
+    let (year, month, day) = (year as i64, month as u32, day as u32);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+/// A `dd/mm/yyyy` date, as days since the epoch.
+pub fn parse_body_date(data: &[u8]) -> Option<i64> {
+    if data.len() != 10 || data[2] != b'/' || data[5] != b'/' {
+        return None;
+    }
+    civil(
+        digits(&data[6..10])?,
+        digits(&data[3..5])?,
+        digits(&data[0..2])?,
+    )
+}
+
+/// A `Date:` header value such as `Sat, 18 Nov 2023 20:22:20 +0800`, as unix
+/// time and the offset of the sender's zone in seconds.
+pub fn parse_header_date(data: &[u8]) -> Option<(u64, i64)> {
+    let mut parts = data.split(|&c| c == b' ');
+
+    let weekday = parts.next()?;
+    if weekday.len() != 4 || weekday[3] != b',' {
+        return None;
+    }
+    let day = parts.next()?;
+    if day.len() > 2 {
+        return None;
+    }
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| &m[..] == month)? as u64 + 1;
+    let year = parts.next()?;
+    if year.len() != 4 {
+        return None;
+    }
+    let days = civil(digits(year)?, month, digits(day)?)?;
+    if WEEKDAYS[(days + 4).rem_euclid(7) as usize][..] != weekday[..3] {
+        return None;
+    }
+
+    let time = parts.next()?;
+    if time.len() != 8 || time[2] != b':' || time[5] != b':' {
+        return None;
+    }
+    let (hour, minute, second) = (
+        digits(&time[0..2])?,
+        digits(&time[3..5])?,
+        digits(&time[6..8])?,
+    );
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let zone = parts.next()?;
+    if zone.len() != 5 || parts.next().is_some() {
+        return None;
+    }
+    let sign = match zone[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let (zone_hour, zone_minute) = (digits(&zone[1..3])?, digits(&zone[3..5])?);
+    if zone_minute > 59 {
+        return None;
+    }
+    let offset = sign * (zone_hour * 3600 + zone_minute * 60) as i64;
+
+    let local = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    let timestamp = u64::try_from(local - offset).ok()?;
+    Some((timestamp, offset))
+}
+
+/// The DKIM `t=` tag: decimal unix time without leading zeros.
+pub fn parse_unix_time(data: &[u8]) -> Option<u64> {
+    if data.len() > 1 && data[0] == b'0' {
+        return None;
+    }
+    digits(data).filter(|_| data.len() <= 12)
+}
+
+/// Check that the three dates describe the same moment, and return the unix
+/// time of the `Date:` header and of the signature.
+///
+/// The body date must be the calendar day of the header in the sender's
+/// zone, and the signature must follow the header within
+/// `MAX_SIGNING_DELAY`, allowing for `MAX_CLOCK_SKEW`.
+pub fn check(date_body: &[u8], date_head: &[u8], dkim_timestamp: &[u8]) -> Option<(u64, u64)> {
+    let day = parse_body_date(date_body)?;
+    let (timestamp, offset) = parse_header_date(date_head)?;
+    let signed = parse_unix_time(dkim_timestamp)?;
+
+    if (timestamp as i64 + offset).div_euclid(86400) != day {
+        return None;
+    }
+    if signed + MAX_CLOCK_SKEW < timestamp || signed > timestamp + MAX_SIGNING_DELAY {
+        return None;
+    }
+    Some((timestamp, signed))
+}
+
+/// Bounds on the DKIM signing time, as inclusive unix times, so that an old
+/// receipt cannot be presented for a new order.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct FreshnessWindow {
+    pub not_before: Option<u64>,
+    pub not_after: Option<u64>,
+}
+
+impl FreshnessWindow {
+    pub fn contains(&self, time: u64) -> bool {
+        self.not_before.map_or(true, |t| time >= t) && self.not_after.map_or(true, |t| time <= t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    const DATE_HEAD: &[u8] = b"Sat, 18 Nov 2023 20:22:20 +0800";
+    const TIMESTAMP: u64 = 1700310140;
+
+    #[test]
+    fn header_date_of_the_sample() {
+        assert_eq!(parse_header_date(DATE_HEAD), Some((TIMESTAMP, 8 * 3600)));
+        assert_eq!(
+            parse_header_date(b"Sat, 18 Nov 2023 04:22:20 -0800"),
+            Some((TIMESTAMP, -8 * 3600))
+        );
+    }
+
+    #[test]
+    fn weekday_must_match_the_date() {
+        assert_eq!(parse_header_date(b"Fri, 18 Nov 2023 20:22:20 +0800"), None);
+        assert_eq!(parse_header_date(b"Sun, 18 Nov 2023 20:22:20 +0800"), None);
+        assert_eq!(parse_header_date(b"sat, 18 Nov 2023 20:22:20 +0800"), None);
+    }
+
+    #[test]
+    fn leap_days() {
+        assert!(parse_header_date(b"Thu, 29 Feb 2024 12:00:00 +0000").is_some());
+        assert!(parse_header_date(b"Tue, 29 Feb 2000 12:00:00 +0000").is_some());
+        assert_eq!(parse_body_date(b"29/02/2024"), Some(19782));
+        assert_eq!(parse_body_date(b"29/02/2023"), None);
+        assert_eq!(parse_body_date(b"29/02/1900"), None);
+        assert_eq!(parse_body_date(b"31/04/2023"), None);
+        assert_eq!(parse_body_date(b"00/01/2023"), None);
+        assert_eq!(parse_body_date(b"01/13/2023"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_header_dates() {
+        for date in [
+            &b"Sat, 18 Nov 2023 20:22:20"[..],
+            b"Sat, 18 Nov 2023 24:00:00 +0800",
+            b"Sat, 18 Nov 2023 20:60:00 +0800",
+            b"Sat, 18 Nov 2023 20:22:20 +0860",
+            b"Sat, 18 Nov 2023 20:22:20 0800",
+            b"Sat, 18 Nov 2023 20:22:20 +0800 ",
+            b"Sat, 18 nov 2023 20:22:20 +0800",
+            b"Sat,  18 Nov 2023 20:22:20 +0800",
+            b"Sat 18 Nov 2023 20:22:20 +0800",
+        ] {
+            assert_eq!(parse_header_date(date), None, "{:?}", date);
+        }
+    }
+
+    #[test]
+    fn body_date_is_the_day_in_the_senders_zone() {
+        // 00:30 on the 19th in Hong Kong is still the 18th in UTC.
+        let head = b"Sun, 19 Nov 2023 00:30:00 +0800";
+        let signed = b"1700325000";
+        assert_eq!(
+            check(b"19/11/2023", head, signed),
+            Some((1700325000, 1700325000))
+        );
+        assert_eq!(check(b"18/11/2023", head, signed), None);
+
+        let head = b"Sun, 31 Dec 2023 23:59:59 +0000";
+        assert!(check(b"31/12/2023", head, b"1704067199").is_some());
+        assert_eq!(check(b"01/01/2024", head, b"1704067199"), None);
+    }
+
+    #[test]
+    fn signing_time_edges() {
+        let check = |signed: u64| check(b"18/11/2023", DATE_HEAD, signed.to_string().as_bytes());
+        assert_eq!(check(TIMESTAMP), Some((TIMESTAMP, TIMESTAMP)));
+        assert!(check(1700310181).is_some());
+        assert!(check(TIMESTAMP - MAX_CLOCK_SKEW).is_some());
+        assert_eq!(check(1700309840), Some((TIMESTAMP, 1700309840)));
+        assert_eq!(check(1700309839), None);
+        assert!(check(TIMESTAMP + MAX_SIGNING_DELAY).is_some());
+        assert_eq!(check(TIMESTAMP + MAX_SIGNING_DELAY + 1), None);
+    }
+
+    #[test]
+    fn unix_time_has_no_leading_zeros() {
+        assert_eq!(parse_unix_time(b"0"), Some(0));
+        assert_eq!(parse_unix_time(b"01700310181"), None);
+        assert_eq!(parse_unix_time(b""), None);
+        assert_eq!(parse_unix_time(b"1700310181 "), None);
+    }
+}
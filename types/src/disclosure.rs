@@ -0,0 +1,143 @@
+// How a field of the receipt appears in the journal: in the clear, not at
+// all, or as a salted commitment.
+
+use crate::amount::{AmountDisclosure, AmountPredicate};
+use crate::template::Field;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+pub const DOMAIN: &[u8] = b"fps-r0/commitment/v1";
+
+pub const SALT_DOMAIN: &[u8] = b"fps-r0/salt/v1";
+
+pub const POLICY_DOMAIN: &[u8] = b"fps-r0/policy/v1";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disclosure {
+    Hide,
+    Reveal,
+    Commit,
+}
+
+impl Disclosure {
+    fn tag(&self) -> u8 {
+        match self {
+            Disclosure::Hide => 0,
+            Disclosure::Reveal => 1,
+            Disclosure::Commit => 2,
+        }
+    }
+}
+
+/// A text field as it appears in the journal, or with `T = u64` a number,
+/// which is committed as eight little-endian bytes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Disclosed<T = Vec<u8>> {
+    Hidden,
+    Revealed(T),
+    /// SHA-256 of `preimage(field, salt, value)`.
+    Committed([u8; 32]),
+}
+
+/// Each field has its own salt, so opening one commitment does not help to
+/// brute-force the others.
+pub fn salt_preimage(seed: &[u8; 32], field: Field) -> Vec<u8> {
+    crate::preimage(SALT_DOMAIN, &[seed, &[field.tag()]])
+}
+
+pub fn preimage(field: Field, salt: &[u8; 32], value: &[u8]) -> Vec<u8> {
+    crate::preimage(DOMAIN, &[&[field.tag()], salt, value])
+}
+
+/// What the journal says about each field, chosen per proof.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisclosurePolicy {
+    pub amount: AmountDisclosure,
+    pub name: Disclosure,
+    pub email: Disclosure,
+    pub receiver: Disclosure,
+    pub receipt_number: Disclosure,
+    pub message_id: Disclosure,
+    pub comment_line: Disclosure,
+    /// The unix time of the `Date:` header, committed with the salt of
+    /// `Field::DateHead`.
+    pub timestamp: Disclosure,
+    /// Commit the hash of the normalized receiver address, see `receiver`.
+    pub bind_receiver: bool,
+}
+
+impl Default for DisclosurePolicy {
+    /// The amount, the masked email, the receipt number and the time in
+    /// the clear, and nothing else.
+    fn default() -> Self {
+        DisclosurePolicy {
+            amount: AmountDisclosure::Reveal,
+            name: Disclosure::Hide,
+            email: Disclosure::Reveal,
+            receiver: Disclosure::Hide,
+            receipt_number: Disclosure::Reveal,
+            message_id: Disclosure::Hide,
+            comment_line: Disclosure::Hide,
+            timestamp: Disclosure::Reveal,
+            bind_receiver: false,
+        }
+    }
+}
+
+impl DisclosurePolicy {
+    /// The text fields covered by the policy and how each is disclosed.
+    pub fn fields(&self) -> [(Field, Disclosure); 6] {
+        [
+            (Field::Name, self.name),
+            (Field::Email, self.email),
+            (Field::Receiver, self.receiver),
+            (Field::ReceiptNumber, self.receipt_number),
+            (Field::MessageId, self.message_id),
+            (Field::CommentLine, self.comment_line),
+        ]
+    }
+
+    /// The fields whose salt the prover needs to open the journal.
+    pub fn committed(&self) -> Vec<Field> {
+        let mut out: Vec<Field> = self
+            .fields()
+            .into_iter()
+            .filter(|(_, disclosure)| *disclosure == Disclosure::Commit)
+            .map(|(field, _)| field)
+            .collect();
+        if self.amount == AmountDisclosure::Commit {
+            out.push(Field::Amount);
+        }
+        if self.timestamp == Disclosure::Commit {
+            out.push(Field::DateHead);
+        }
+        out
+    }
+
+    /// The amount disclosure as a kind byte and two bounds, a field tag and a
+    /// disclosure byte per field and for the timestamp, and `bind_receiver`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(17 + 14 + 1);
+        let (kind, bounds) = match self.amount {
+            AmountDisclosure::Reveal => (0, [0, 0]),
+            AmountDisclosure::Predicate(AmountPredicate::AtLeast(min)) => (1, [min, 0]),
+            AmountDisclosure::Predicate(AmountPredicate::AtMost(max)) => (2, [0, max]),
+            AmountDisclosure::Predicate(AmountPredicate::Between(min, max)) => (3, [min, max]),
+            AmountDisclosure::Predicate(AmountPredicate::Exactly(value)) => (4, [value, value]),
+            AmountDisclosure::Hide => (5, [0, 0]),
+            AmountDisclosure::Commit => (6, [0, 0]),
+        };
+        out.push(kind);
+        for bound in bounds {
+            out.extend_from_slice(&bound.to_le_bytes());
+        }
+        for (field, disclosure) in self.fields() {
+            out.push(field.tag());
+            out.push(disclosure.tag());
+        }
+        out.push(Field::DateHead.tag());
+        out.push(self.timestamp.tag());
+        out.push(self.bind_receiver as u8);
+        crate::preimage(POLICY_DOMAIN, &[&out])
+    }
+}
@@ -0,0 +1,275 @@
+// Types shared by the host, the guest and any verifier of the journal.
+//
+// Both sides serialize these with the RISC Zero serde format, so defining
+// them once here is what keeps the witness that the host writes and the
+// journal that the guest commits readable on the other side.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+pub mod aggregate;
+pub mod amount;
+pub mod cycles;
+pub mod date;
+pub mod disclosure;
+pub mod mask;
+pub mod nullifier;
+pub mod receiver;
+pub mod sum;
+pub mod template;
+pub mod validate;
+
+use amount::AmountOutput;
+use date::FreshnessWindow;
+use disclosure::{Disclosed, DisclosurePolicy};
+
+/// The input of a hash committed by a guest: the domain, then each part
+/// prefixed with its length as a little-endian `u32`.
+pub fn preimage(domain: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let len = parts.iter().map(|part| 4 + part.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(domain.len() + len);
+    out.extend_from_slice(domain);
+    for part in parts {
+        out.extend_from_slice(&(part.len() as u32).to_le_bytes());
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Witness {
+    pub template_id: u32,
+    pub comment_line: Vec<u8>,
+    pub amount: Vec<u8>,
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    pub date_body: Vec<u8>,
+    pub date_head: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub message_id: Vec<u8>,
+    pub dkim_timestamp: Vec<u8>,
+    pub bh_base64: Vec<u8>,
+    pub receipt_number: Vec<u8>,
+    pub signature_mont: Vec<u8>,
+    pub extra_parts: Vec<Vec<u8>>,
+}
+
+impl template::Fields for Witness {
+    fn field(&self, field: template::Field) -> &[u8] {
+        use template::Field;
+        match field {
+            Field::CommentLine => &self.comment_line,
+            Field::Amount => &self.amount,
+            Field::Name => &self.name,
+            Field::Email => &self.email,
+            Field::DateBody => &self.date_body,
+            Field::DateHead => &self.date_head,
+            Field::Receiver => &self.receiver,
+            Field::MessageId => &self.message_id,
+            Field::DkimTimestamp => &self.dkim_timestamp,
+            Field::BhBase64 => &self.bh_base64,
+            Field::ReceiptNumber => &self.receipt_number,
+        }
+    }
+
+    fn extra_parts(&self) -> &[Vec<u8>] {
+        &self.extra_parts
+    }
+}
+
+/// The transaction a proof is made for, supplied by the application and
+/// echoed into the journal, so that a proof copied to another order or
+/// another chain does not verify there. Any part may be left empty.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct BindingContext {
+    pub order_id: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub chain_id: Option<u64>,
+    /// The public key or address that should be credited.
+    pub recipient: Vec<u8>,
+}
+
+/// Public choices made by the prover, read by the guest after the witness.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct ProofParams {
+    pub policy: DisclosurePolicy,
+    /// Required range of the DKIM signing time.
+    pub freshness: FreshnessWindow,
+    /// Mixed into the nullifier, so that applications do not share one.
+    pub app_id: Vec<u8>,
+    pub context: BindingContext,
+}
+
+/// Private inputs that are not part of the email, read by the guest after
+/// the params.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Secrets {
+    /// Seed of the salts of committed fields. Kept by the prover.
+    pub salt_seed: [u8; 32],
+    /// The payee name and email in full, to be checked against the masked
+    /// values in the receipt.
+    pub full_name: Option<Vec<u8>>,
+    pub full_email: Option<Vec<u8>>,
+}
+
+/// Bumped whenever the layout of `PublicOutput` changes.
+pub const JOURNAL_VERSION: u32 = 12;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PublicOutput {
+    pub version: u32,
+    pub template_id: u32,
+    pub template_hash: [u8; 32],
+    pub dkim_domain: Vec<u8>,
+    pub dkim_selector: Vec<u8>,
+    pub key_hash: [u8; 32],
+    /// ISO 4217 code, e.g. `HKD`.
+    pub currency: Vec<u8>,
+    /// In minor units, e.g. cents, or only a predicate on it.
+    pub amount: AmountOutput,
+    /// The payee as shown in the receipt, masked by the bank.
+    pub name: Disclosed,
+    pub email: Disclosed,
+    /// SHA-256 of `mask::preimage` of the full name and email, if they
+    /// were supplied and match the masks.
+    pub full_name_hash: Option<[u8; 32]>,
+    pub full_email_hash: Option<[u8; 32]>,
+    /// The `To:` header of the receipt.
+    pub receiver: Disclosed,
+    /// SHA-256 of `receiver::preimage` of the normalized `To:` address, if
+    /// the policy binds the receiver.
+    pub receiver_hash: Option<[u8; 32]>,
+    /// Unix time of the `Date:` header, cross-checked against the date in
+    /// the body and the DKIM signing time.
+    pub timestamp: Disclosed<u64>,
+    /// The window the signing time was checked against.
+    pub freshness: FreshnessWindow,
+    pub receipt_number: Disclosed,
+    pub message_id: Disclosed,
+    pub comment_line: Disclosed,
+    /// SHA-256 of `DisclosurePolicy::encode`, so a verifier can check that
+    /// the policy it asked for was applied.
+    pub policy_hash: [u8; 32],
+    pub app_id: Vec<u8>,
+    /// Same for every proof of this receipt under `app_id`.
+    pub nullifier: [u8; 32],
+    /// As given in `ProofParams`; checking it is up to the verifier.
+    pub context: BindingContext,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::amount::AmountPredicate;
+    use alloc::vec;
+    use risc0_zkvm::serde::{from_slice, to_vec};
+
+    /// The fields of the receipt that the repository was developed against.
+    pub fn sample() -> Witness {
+        Witness {
+            template_id: 1,
+            comment_line: b"2772651_343290592.1700310140503".to_vec(),
+            amount: b"10.00".to_vec(),
+            name: b"CHEN W******".to_vec(),
+            email: b"w********@chenweikeng.com".to_vec(),
+            date_body: b"18/11/2023".to_vec(),
+            date_head: b"Sat, 18 Nov 2023 20:22:20 +0800".to_vec(),
+            receiver: b"\"WEIKENG@CHENWEIKENG.COM\" <WEIKENG@CHENWEIKENG.COM>".to_vec(),
+            message_id: b"<101958940.2772652.1700310140503.JavaMail.1000830000@hk-boa-15-5f8fl>"
+                .to_vec(),
+            dkim_timestamp: b"1700310181".to_vec(),
+            bh_base64: b"hJ/+UNkf1BHOUMaYhrzDzD3adraujFmKjZajNWOLYT4=".to_vec(),
+            receipt_number: b"2311-182022218700".to_vec(),
+            signature_mont: (0..=255).collect(),
+            extra_parts: Vec::new(),
+        }
+    }
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        from_slice(&to_vec(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn preimage_separates_the_parts() {
+        let split = |at: usize| preimage(b"d", &[&b"abc"[..at], &b"abc"[at..]]);
+        assert_ne!(split(1), split(2));
+        assert_ne!(preimage(b"d", &[b"ab"]), preimage(b"d", &[b"a", b"b"]));
+    }
+
+    #[test]
+    fn witness_round_trips() {
+        let witness = sample();
+        assert_eq!(round_trip(&witness), witness);
+
+        let witness = Witness {
+            template_id: 2,
+            extra_parts: vec![b"Content-Type: text/html\r\n\r\n<p>".to_vec(), Vec::new()],
+            ..sample()
+        };
+        assert_eq!(round_trip(&witness), witness);
+    }
+
+    #[test]
+    fn public_output_round_trips() {
+        let output = PublicOutput {
+            version: JOURNAL_VERSION,
+            template_id: 1,
+            template_hash: [1; 32],
+            dkim_domain: b"sc.com".to_vec(),
+            dkim_selector: b"k06k22gbledmsml".to_vec(),
+            key_hash: [2; 32],
+            currency: b"HKD".to_vec(),
+            amount: AmountOutput::Predicate {
+                predicate: AmountPredicate::Between(100, 2000),
+                holds: true,
+            },
+            name: Disclosed::Hidden,
+            email: Disclosed::Revealed(b"w********@chenweikeng.com".to_vec()),
+            full_name_hash: None,
+            full_email_hash: Some([3; 32]),
+            receiver: Disclosed::Committed([4; 32]),
+            receiver_hash: Some([5; 32]),
+            timestamp: Disclosed::Committed([8; 32]),
+            freshness: FreshnessWindow {
+                not_before: Some(1700000000),
+                not_after: None,
+            },
+            receipt_number: Disclosed::Revealed(b"2311-182022218700".to_vec()),
+            message_id: Disclosed::Hidden,
+            comment_line: Disclosed::Hidden,
+            policy_hash: [6; 32],
+            app_id: b"shop".to_vec(),
+            nullifier: [7; 32],
+            context: BindingContext {
+                order_id: b"order-1".to_vec(),
+                nonce: vec![],
+                chain_id: Some(1),
+                recipient: b"0xabc".to_vec(),
+            },
+        };
+        assert_eq!(round_trip(&output), output);
+    }
+
+    #[test]
+    fn params_and_secrets_round_trip() {
+        let params = ProofParams {
+            app_id: b"shop".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(round_trip(&params), params);
+
+        let secrets = Secrets {
+            salt_seed: [9; 32],
+            full_name: Some(b"CHEN WEIKENG".to_vec()),
+            full_email: None,
+        };
+        assert_eq!(round_trip(&secrets), secrets);
+    }
+}
@@ -0,0 +1,65 @@
+// Full payee names and emails checked against the bank's masks, such as
+// `CHEN W******` and `w********@chenweikeng.com`.
+
+use crate::template::Field;
+use alloc::vec::Vec;
+
+pub const MASK: u8 = b'*';
+
+pub const DOMAIN: &[u8] = b"fps-r0/unmasked/v1";
+
+/// Whether `full` could have been masked into `masked`: the same length, and
+/// every character that the bank did not replace by `*` is the same.
+pub fn consistent(masked: &[u8], full: &[u8]) -> bool {
+    masked.len() == full.len() && masked.iter().zip(full).all(|(&m, &f)| m == MASK || m == f)
+}
+
+/// Unsalted, so that the merchant can recompute it from the value it knows.
+pub fn preimage(field: Field, full: &[u8]) -> Vec<u8> {
+    crate::preimage(DOMAIN, &[&[field.tag()], full])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &[u8] = b"CHEN W******";
+    const EMAIL: &[u8] = b"w********@chenweikeng.com";
+
+    #[test]
+    fn full_values_of_the_sample() {
+        assert!(consistent(NAME, b"CHEN WEIKENG"));
+        assert!(consistent(EMAIL, b"weikeng12@chenweikeng.com"));
+    }
+
+    #[test]
+    fn rejects_a_wrong_length() {
+        assert!(!consistent(NAME, b"CHEN WEIKENGG"));
+        assert!(!consistent(NAME, b"CHEN WEIKEN"));
+        assert!(!consistent(EMAIL, b"weikeng@chenweikeng.com"));
+        assert!(!consistent(EMAIL, b""));
+    }
+
+    #[test]
+    fn rejects_a_wrong_unmasked_character() {
+        assert!(!consistent(NAME, b"CHAN WEIKENG"));
+        assert!(!consistent(NAME, b"CHEN XEIKENG"));
+        assert!(!consistent(EMAIL, b"x********@chenweikeng.com"));
+        assert!(!consistent(EMAIL, b"weikeng12@chenweikeng.org"));
+    }
+
+    #[test]
+    fn unmasked_characters_are_compared_exactly() {
+        assert!(!consistent(EMAIL, b"weikeng12@ChenWeiKeng.com"));
+        assert!(!consistent(NAME, b"Chen WEIKENG"));
+        assert!(!consistent(NAME, b" CHEN WEIKEN"));
+        assert!(!consistent(EMAIL, b"weikeng1@chenweikeng.com "));
+    }
+
+    #[test]
+    fn preimage_depends_on_the_field() {
+        let name = preimage(Field::Name, b"CHEN WEIKENG");
+        assert_ne!(name, preimage(Field::Email, b"CHEN WEIKENG"));
+        assert!(name.starts_with(DOMAIN));
+    }
+}
@@ -0,0 +1,11 @@
+// Nullifiers, which let a verifier recognise a receipt already redeemed.
+
+use alloc::vec::Vec;
+
+pub const DOMAIN: &[u8] = b"fps-r0/nullifier/v1";
+
+/// Only the application id is chosen by the prover; an empty one makes the
+/// nullifier global.
+pub fn preimage(app_id: &[u8], message_id: &[u8], receipt_number: &[u8]) -> Vec<u8> {
+    crate::preimage(DOMAIN, &[app_id, message_id, receipt_number])
+}
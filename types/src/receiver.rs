@@ -0,0 +1,104 @@
+// The address the receipt was delivered to, from the `To:` header.
+
+use alloc::vec::Vec;
+
+pub const DOMAIN: &[u8] = b"fps-r0/receiver/v1";
+
+/// The address in a `To:` value such as `"NAME" <User@Example.com>` or a bare
+/// `user@example.com`, lowercased.
+///
+/// Only a single address with exactly one `@` and no whitespace is
+/// accepted; a display name is allowed only in front of an address in angle
+/// brackets.
+pub fn normalize(value: &[u8]) -> Option<Vec<u8>> {
+    let address = match value.last() {
+        Some(b'>') => {
+            let open = value.iter().rposition(|&c| c == b'<')?;
+            let display_name = match &value[..open] {
+                [] => true,
+                [b'"', name @ .., b'"', b' '] => !name
+                    .iter()
+                    .any(|c| matches!(c, b'"' | b'\\' | b'<' | b'>' | b',')),
+                _ => false,
+            };
+            if !display_name {
+                return None;
+            }
+            &value[open + 1..value.len() - 1]
+        }
+        _ => value,
+    };
+    if address.iter().filter(|&&c| c == b'@').count() != 1
+        || address.first() == Some(&b'@')
+        || address.last() == Some(&b'@')
+        || address
+            .iter()
+            .any(|&c| c <= b' ' || c >= 0x7f || matches!(c, b'<' | b'>' | b',' | b'"'))
+    {
+        return None;
+    }
+    Some(address.to_ascii_lowercase())
+}
+
+/// Unsalted, so anyone can test a guessed address against it; that is why
+/// the binding is opt-in.
+pub fn preimage(address: &[u8]) -> Vec<u8> {
+    crate::preimage(DOMAIN, &[address])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_of_the_sample() {
+        let to = b"\"WEIKENG@CHENWEIKENG.COM\" <WEIKENG@CHENWEIKENG.COM>";
+        assert_eq!(normalize(to).unwrap(), b"weikeng@chenweikeng.com");
+        assert_eq!(
+            normalize(b"<weikeng@chenweikeng.com>").unwrap(),
+            b"weikeng@chenweikeng.com"
+        );
+    }
+
+    #[test]
+    fn case_does_not_matter() {
+        assert_eq!(
+            normalize(b"user@Example.COM"),
+            normalize(b"user@example.com")
+        );
+        assert_eq!(normalize(b"USER@example.com").unwrap(), b"user@example.com");
+    }
+
+    #[test]
+    fn rejects_surrounding_whitespace() {
+        for value in [
+            &b" user@example.com"[..],
+            b"user@example.com ",
+            b"user@example.com\r\n",
+            b"\"A\" <user@example.com> ",
+            b"\"A\" < user@example.com>",
+            b"\"A\" <user@example.com\t>",
+        ] {
+            assert_eq!(normalize(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_anything_but_one_address() {
+        for value in [
+            &b""[..],
+            b"user",
+            b"@example.com",
+            b"user@",
+            b"a@b@example.com",
+            b"a@example.com,b@example.com",
+            b"\"A\" <a@example.com>, <b@example.com>",
+            b"a@example.com <b@example.com",
+            b"a@example.com <b@example.com>",
+            b"\"A\" \"B\" <a@example.com>",
+            b"<>",
+        ] {
+            assert_eq!(normalize(value), None, "{:?}", value);
+        }
+    }
+}
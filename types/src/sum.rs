@@ -0,0 +1,41 @@
+// The total of many receipts to the same payee, proven by the `sum` guest.
+
+use crate::date::FreshnessWindow;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+pub const SET_DOMAIN: &[u8] = b"fps-r0/receipt-set/v1";
+
+/// Read by the `sum` guest after the list of witnesses.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct SumParams {
+    /// Every receipt must show this payee email, as masked by the bank.
+    pub payee: Vec<u8>,
+    /// Range of the `Date:` header of every receipt.
+    pub period: FreshnessWindow,
+    /// The application id of the nullifiers, see `nullifier`.
+    pub app_id: Vec<u8>,
+}
+
+pub const SUM_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SumOutput {
+    pub version: u32,
+    pub key_hash: [u8; 32],
+    pub currency: Vec<u8>,
+    pub payee: Vec<u8>,
+    pub period: FreshnessWindow,
+    pub app_id: Vec<u8>,
+    pub count: u32,
+    /// In minor units.
+    pub total: u64,
+    /// SHA-256 of `set_preimage` of the nullifiers.
+    pub set_commitment: [u8; 32],
+}
+
+/// The nullifiers must be sorted.
+pub fn set_preimage(sorted: &[[u8; 32]]) -> Vec<u8> {
+    let parts: Vec<&[u8]> = sorted.iter().map(|nullifier| &nullifier[..]).collect();
+    crate::preimage(SET_DOMAIN, &parts)
+}
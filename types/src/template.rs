@@ -0,0 +1,452 @@
+// Layouts of the notification emails that the guest can reconstruct.
+//
+// A template is the fixed text of an email with slots for the fields that
+// change from one receipt to the next. The guest fills the slots from the
+// witness, and commits the template id together with a hash of the
+// definition, so that a verifier knows which layout was proven.
+
+use alloc::vec::Vec;
+
+/// A slot in a template, filled from the witness.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Field {
+    CommentLine,
+    Amount,
+    Name,
+    Email,
+    DateBody,
+    DateHead,
+    Receiver,
+    MessageId,
+    DkimTimestamp,
+    BhBase64,
+    ReceiptNumber,
+}
+
+impl Field {
+    /// Stable number of the field, used in the template hash.
+    pub fn tag(&self) -> u8 {
+        match self {
+            Field::CommentLine => 0,
+            Field::Amount => 1,
+            Field::Name => 2,
+            Field::Email => 3,
+            Field::DateBody => 4,
+            Field::DateHead => 5,
+            Field::Receiver => 6,
+            Field::MessageId => 7,
+            Field::DkimTimestamp => 8,
+            Field::BhBase64 => 9,
+            Field::ReceiptNumber => 10,
+        }
+    }
+}
+
+pub enum Piece {
+    Lit(&'static [u8]),
+    Field(Field),
+}
+
+/// Anything that can provide the values of the slots.
+pub trait Fields {
+    fn field(&self, field: Field) -> &[u8];
+
+    /// MIME parts after the text part, hashed but not interpreted.
+    fn extra_parts(&self) -> &[Vec<u8>];
+}
+
+pub struct Template {
+    /// Stable id, never reused for a different layout.
+    pub id: u32,
+    /// The `d=` and `s=` of the DKIM signature, also part of `dkim_header`.
+    pub dkim_domain: &'static [u8],
+    pub dkim_selector: &'static [u8],
+    /// The currency of the amount, also part of `paragraph`.
+    pub currency: &'static [u8],
+    /// The MIME boundary, without the leading `--` of the delimiter lines.
+    pub boundary: &'static [Piece],
+    /// Whether other parts may follow the text part, such as an HTML
+    /// alternative or a nested multipart.
+    pub extra_parts: bool,
+    /// The text part up to the paragraph that carries the payment details.
+    pub body_head: &'static [Piece],
+    /// The payment paragraph, soft-wrapped with quoted-printable `=\r\n`.
+    pub paragraph: &'static [Piece],
+    /// The text part after the paragraph.
+    pub body_tail: &'static [Piece],
+    /// Signed headers, in relaxed canonicalization.
+    pub header: &'static [Piece],
+    /// The DKIM-Signature header, up to and including `b=`.
+    pub dkim_header: &'static [Piece],
+}
+
+/// Quoted-printable lines are at most 76 characters including the `=`;
+/// the sender breaks them after 74.
+pub const WRAP_WIDTH: usize = 74;
+
+fn render(out: &mut Vec<u8>, pieces: &[Piece], fields: &impl Fields) {
+    for piece in pieces {
+        match piece {
+            Piece::Lit(lit) => out.extend_from_slice(lit),
+            Piece::Field(field) => out.extend_from_slice(fields.field(*field)),
+        }
+    }
+}
+
+fn define(out: &mut Vec<u8>, pieces: &[Piece]) {
+    out.extend_from_slice(&(pieces.len() as u32).to_le_bytes());
+    for piece in pieces {
+        match piece {
+            Piece::Lit(lit) => {
+                out.push(0);
+                out.extend_from_slice(&(lit.len() as u32).to_le_bytes());
+                out.extend_from_slice(lit);
+            }
+            Piece::Field(field) => {
+                out.push(1);
+                out.push(field.tag());
+            }
+        }
+    }
+}
+
+impl Template {
+    /// The delimiter line between parts: `--`, the boundary, and no CRLF.
+    pub fn delimiter(&self, fields: &impl Fields) -> Vec<u8> {
+        let mut delimiter = Vec::<u8>::with_capacity(64);
+        delimiter.extend_from_slice(b"--");
+        render(&mut delimiter, self.boundary, fields);
+        delimiter
+    }
+
+    /// Whether the extra parts can be told apart from the boundary
+    /// structure, i.e. none of them contains a delimiter line.
+    pub fn check_parts(&self, fields: &impl Fields) -> bool {
+        let parts = fields.extra_parts();
+        if !self.extra_parts && !parts.is_empty() {
+            return false;
+        }
+
+        let mut marker = Vec::<u8>::with_capacity(64);
+        marker.extend_from_slice(b"\r\n");
+        marker.extend_from_slice(&self.delimiter(fields));
+
+        for part in parts {
+            if part.starts_with(&marker[2..]) {
+                return false;
+            }
+            if part.windows(marker.len()).any(|w| w == marker.as_slice()) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether the delimiter starts a line of the rendered body only where
+    /// the template puts one: before the text part, before each extra part,
+    /// and at the close.
+    ///
+    /// Any other line that begins with the delimiter, even with more text
+    /// after it, would let a MIME parser see a different set of parts in the
+    /// same signed bytes.
+    pub fn check_delimiters(&self, body: &[u8], fields: &impl Fields) -> bool {
+        let delimiter = self.delimiter(fields);
+        let lines = body
+            .split(|&c| c == b'\n')
+            .filter(|line| line.starts_with(&delimiter))
+            .count();
+        lines == fields.extra_parts().len() + 2
+    }
+
+    pub fn render_body(&self, fields: &impl Fields) -> Vec<u8> {
+        let delimiter = self.delimiter(fields);
+
+        let mut paragraph = Vec::<u8>::with_capacity(512);
+        render(&mut paragraph, self.paragraph, fields);
+
+        let mut body = Vec::<u8>::with_capacity(2048);
+        body.extend_from_slice(&delimiter);
+        body.extend_from_slice(b"\r\n");
+        render(&mut body, self.body_head, fields);
+
+        let mut cur = 0;
+        let mut paragraph_len = paragraph.len();
+        while paragraph_len > WRAP_WIDTH {
+            body.extend_from_slice(&paragraph[cur..cur + WRAP_WIDTH]);
+            body.extend_from_slice(b"=\r\n");
+            cur += WRAP_WIDTH;
+            paragraph_len -= WRAP_WIDTH;
+        }
+        if paragraph_len != 0 {
+            body.extend_from_slice(&paragraph[cur..cur + paragraph_len]);
+        }
+
+        render(&mut body, self.body_tail, fields);
+
+        for part in fields.extra_parts() {
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&delimiter);
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(part);
+        }
+
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(&delimiter);
+        body.extend_from_slice(b"--\r\n");
+        body
+    }
+
+    pub fn render_header(&self, fields: &impl Fields) -> Vec<u8> {
+        let mut header = Vec::<u8>::with_capacity(512);
+        render(&mut header, self.header, fields);
+        header
+    }
+
+    pub fn render_dkim_header(&self, fields: &impl Fields) -> Vec<u8> {
+        let mut dkim_header = Vec::<u8>::with_capacity(256);
+        render(&mut dkim_header, self.dkim_header, fields);
+        dkim_header
+    }
+
+    /// Unambiguous encoding of the layout, hashed into the journal.
+    pub fn definition(&self) -> Vec<u8> {
+        let mut out = Vec::<u8>::with_capacity(2048);
+        out.extend_from_slice(&self.id.to_le_bytes());
+        out.extend_from_slice(&(WRAP_WIDTH as u32).to_le_bytes());
+        define(&mut out, self.boundary);
+        out.push(self.extra_parts as u8);
+        define(&mut out, self.body_head);
+        define(&mut out, self.paragraph);
+        define(&mut out, self.body_tail);
+        define(&mut out, self.header);
+        define(&mut out, self.dkim_header);
+        out
+    }
+}
+
+pub const SC_DKIM_DOMAIN: &[u8] = b"sc.com";
+pub const SC_DKIM_SELECTOR: &[u8] = b"k06k22gbledmsml";
+
+const SC_BOUNDARY: &[Piece] = &[Piece::Lit(b"----=_Part_"), Piece::Field(Field::CommentLine)];
+
+const SC_PAY_SEND_MONEY_HEAD: &[Piece] = &[
+    Piece::Lit(b"Content-Type: text/plain; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\nDear Valued Client,\r\n\r\nThank you for using Standard Chartered Pay(\"SC Pay\") service.\r\n\r\n"),
+];
+
+const SC_PAY_SEND_MONEY_PARAGRAPH: &[Piece] = &[
+    Piece::Lit(b"Your payment to send HKD "),
+    Piece::Field(Field::Amount),
+    Piece::Lit(b" to "),
+    Piece::Field(Field::Name),
+    Piece::Lit(b", "),
+    Piece::Field(Field::Email),
+    Piece::Lit(b" via SC Pay has been transferred on "),
+    Piece::Field(Field::DateBody),
+    Piece::Lit(b" successfully."),
+];
+
+const SC_PAY_SEND_MONEY_TAIL: &[Piece] = &[
+    Piece::Lit(b"\r\n\r\nIf you didn=E2=80=99t make this payment, please contact our Customer Servi=\r\nce Hotline at (852) 2886 8868 immediately.\r\n\r\nYours sincerely,\r\nStandard Chartered Bank (Hong Kong) Limited\r\n\r\nThis email and any attachments are confidential and may also be privileged=\r\n. If you are not the intended recipient, please delete all copies and noti=\r\nfy the sender immediately. You may wish to refer to the incorporation deta=\r\nils of Standard Chartered PLC, Standard Chartered Bank and their subsidiar=\r\nies together with Standard Chartered Bank=E2=80=99s Privacy Policy via our=\r\n public website."),
+];
+
+const SC_DKIM_HEADER: &[Piece] = &[
+    Piece::Lit(b"dkim-signature:v=1; a=rsa-sha256; c=relaxed/relaxed; d="),
+    Piece::Lit(SC_DKIM_DOMAIN),
+    Piece::Lit(b"; s="),
+    Piece::Lit(SC_DKIM_SELECTOR),
+    Piece::Lit(b"; t="),
+    Piece::Field(Field::DkimTimestamp),
+    Piece::Lit(b"; i=@sc.com; bh="),
+    Piece::Field(Field::BhBase64),
+    Piece::Lit(b"; h=Date:From:To:Message-ID:Subject:MIME-Version:Content-Type; b="),
+];
+
+/// The signed headers of the receipt, sent as `multipart/<subtype>`.
+const fn sc_pay_send_money_header(subtype: &'static [u8]) -> [Piece; 13] {
+    [
+        Piece::Lit(b"date:"),
+        Piece::Field(Field::DateHead),
+        Piece::Lit(b"\r\nfrom:Standard Chartered Alerts <OnlineBanking.HK@sc.com>\r\nto:"),
+        Piece::Field(Field::Receiver),
+        Piece::Lit(b"\r\nmessage-id:"),
+        Piece::Field(Field::MessageId),
+        Piece::Lit(b"\r\nsubject:=?UTF-8?Q?Send_Money_via_Standard_Chartered_?= =?UTF-8?Q?Pay_=E2=80=93_Receipt_No._"),
+        Piece::Field(Field::ReceiptNumber),
+        Piece::Lit(b"?=\r\nmime-version:1.0\r\ncontent-type:multipart/"),
+        Piece::Lit(subtype),
+        Piece::Lit(b"; boundary=\"----=_Part_"),
+        Piece::Field(Field::CommentLine),
+        Piece::Lit(b"\"\r\n"),
+    ]
+}
+
+const SC_PAY_SEND_MONEY_MIXED_HEADER: [Piece; 13] = sc_pay_send_money_header(b"mixed");
+
+const SC_PAY_SEND_MONEY_ALTERNATIVE_HEADER: [Piece; 13] = sc_pay_send_money_header(b"alternative");
+
+/// Standard Chartered Hong Kong, "Send Money via SC Pay" receipt.
+pub const SC_PAY_SEND_MONEY_V1: Template = Template {
+    id: 1,
+    dkim_domain: SC_DKIM_DOMAIN,
+    dkim_selector: SC_DKIM_SELECTOR,
+    currency: b"HKD",
+    boundary: SC_BOUNDARY,
+    extra_parts: false,
+    body_head: SC_PAY_SEND_MONEY_HEAD,
+    paragraph: SC_PAY_SEND_MONEY_PARAGRAPH,
+    body_tail: SC_PAY_SEND_MONEY_TAIL,
+    header: &SC_PAY_SEND_MONEY_MIXED_HEADER,
+    dkim_header: SC_DKIM_HEADER,
+};
+
+/// The same receipt sent as multipart/alternative, where the text part is
+/// followed by an HTML alternative or other parts that are not interpreted.
+///
+/// No signed email of this shape has been seen yet; it is template 1 with
+/// the other subtype.
+pub const SC_PAY_SEND_MONEY_ALTERNATIVE_V1: Template = Template {
+    id: 2,
+    extra_parts: true,
+    header: &SC_PAY_SEND_MONEY_ALTERNATIVE_HEADER,
+    ..SC_PAY_SEND_MONEY_V1
+};
+
+pub const TEMPLATES: &[&Template] = &[&SC_PAY_SEND_MONEY_V1, &SC_PAY_SEND_MONEY_ALTERNATIVE_V1];
+
+pub fn by_id(id: u32) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.id == id).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::sample;
+    use crate::validate;
+    use crate::Witness;
+    use alloc::vec;
+
+    const DELIMITER: &[u8] = b"------=_Part_2772651_343290592.1700310140503";
+
+    fn alternative(parts: Vec<Vec<u8>>) -> Witness {
+        Witness {
+            template_id: SC_PAY_SEND_MONEY_ALTERNATIVE_V1.id,
+            extra_parts: parts,
+            ..sample()
+        }
+    }
+
+    fn accepted(template: &Template, witness: &Witness) -> bool {
+        let body = template.render_body(witness);
+        validate::check(witness).is_ok()
+            && template.check_parts(witness)
+            && template.check_delimiters(&body, witness)
+    }
+
+    #[test]
+    fn sample_is_accepted() {
+        assert!(accepted(&SC_PAY_SEND_MONEY_V1, &sample()));
+        let html = b"Content-Type: text/html\r\n\r\n<p>-- not a delimiter</p>".to_vec();
+        assert!(accepted(
+            &SC_PAY_SEND_MONEY_ALTERNATIVE_V1,
+            &alternative(vec![html])
+        ));
+    }
+
+    #[test]
+    fn alternative_differs_from_mixed_only_in_the_subtype() {
+        let mixed = &SC_PAY_SEND_MONEY_V1;
+        let alternative_v1 = &SC_PAY_SEND_MONEY_ALTERNATIVE_V1;
+        let expected = mixed.render_header(&sample());
+        let at = expected.windows(6).position(|w| w == b"/mixed").unwrap();
+        let expected = [&expected[..at], b"/alternative", &expected[at + 6..]].concat();
+        assert_eq!(alternative_v1.render_header(&sample()), expected);
+        assert_eq!(
+            alternative_v1.render_dkim_header(&sample()),
+            mixed.render_dkim_header(&sample())
+        );
+        assert_ne!(alternative_v1.definition(), mixed.definition());
+    }
+
+    #[test]
+    fn alternative_renders_its_extra_parts_between_delimiters() {
+        let template = &SC_PAY_SEND_MONEY_ALTERNATIVE_V1;
+        let html = b"Content-Type: text/html\r\n\r\n<p>Paid</p>".to_vec();
+        let nested = b"Content-Type: multipart/related\r\n\r\n".to_vec();
+        let witness = alternative(vec![html.clone(), nested.clone()]);
+        assert!(template.check_parts(&witness));
+
+        let body = template.render_body(&witness);
+        assert!(template.check_delimiters(&body, &witness));
+        let text = SC_PAY_SEND_MONEY_V1.render_body(&sample());
+        let end = text.len() - DELIMITER.len() - 6;
+        let mut expected = text[..end].to_vec();
+        for part in [html, nested] {
+            expected.extend_from_slice(b"\r\n");
+            expected.extend_from_slice(DELIMITER);
+            expected.extend_from_slice(b"\r\n");
+            expected.extend_from_slice(&part);
+        }
+        expected.extend_from_slice(b"\r\n");
+        expected.extend_from_slice(DELIMITER);
+        expected.extend_from_slice(b"--\r\n");
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn extra_parts_need_a_template_that_allows_them() {
+        let witness = alternative(vec![b"Content-Type: text/html\r\n\r\n".to_vec()]);
+        assert!(!SC_PAY_SEND_MONEY_V1.check_parts(&witness));
+    }
+
+    #[test]
+    fn extra_part_cannot_start_with_the_delimiter() {
+        let template = &SC_PAY_SEND_MONEY_ALTERNATIVE_V1;
+        let mut part = DELIMITER.to_vec();
+        part.extend_from_slice(b"\r\nContent-Type: text/plain\r\n\r\nforged");
+        let witness = alternative(vec![part]);
+        assert!(!template.check_parts(&witness));
+        assert!(!template.check_delimiters(&template.render_body(&witness), &witness));
+    }
+
+    #[test]
+    fn extra_part_cannot_contain_the_delimiter_on_a_later_line() {
+        let template = &SC_PAY_SEND_MONEY_ALTERNATIVE_V1;
+        for newline in [&b"\r\n"[..], b"\n"] {
+            let mut part = b"Content-Type: text/html\r\n\r\n<p>".to_vec();
+            part.extend_from_slice(newline);
+            part.extend_from_slice(DELIMITER);
+            part.extend_from_slice(b"--");
+            let witness = alternative(vec![part]);
+            assert!(!template.check_delimiters(&template.render_body(&witness), &witness));
+        }
+
+        let mut part = b"Content-Type: text/html\r\n\r\n".to_vec();
+        part.extend_from_slice(b"\r\n");
+        part.extend_from_slice(DELIMITER);
+        assert!(!template.check_parts(&alternative(vec![part])));
+    }
+
+    #[test]
+    fn delimiter_with_a_longer_boundary_is_still_a_delimiter_line() {
+        // `1_2.34` starts with `1_2.3`, so a MIME parser reading the
+        // boundary `1_2.3` sees a delimiter where the part says `1_2.34`.
+        let template = &SC_PAY_SEND_MONEY_ALTERNATIVE_V1;
+        let witness = Witness {
+            comment_line: b"1_2.3".to_vec(),
+            ..alternative(vec![
+                b"Content-Type: text/html\r\n\r\n\r\n------=_Part_1_2.34\r\n".to_vec(),
+            ])
+        };
+        assert!(validate::check(&witness).is_ok());
+        assert!(!template.check_parts(&witness));
+        assert!(!template.check_delimiters(&template.render_body(&witness), &witness));
+
+        let witness = Witness {
+            comment_line: b"1_2.3".to_vec(),
+            ..alternative(vec![
+                b"Content-Type: text/html\r\n\r\n------=_Part_1_2.34".to_vec()
+            ])
+        };
+        assert!(!template.check_delimiters(&template.render_body(&witness), &witness));
+    }
+}
@@ -0,0 +1,277 @@
+// The shape of every slot, checked before the template is rendered.
+//
+// The template literals only mean what they say if no field can contain
+// text that looks like them, e.g. a CRLF followed by a header, or the
+// closing of the paragraph. Each field is therefore matched in full against
+// an anchored pattern made of literals and bounded runs of a character
+// class, or one of a few such patterns, which is all the receipts need.
+
+use crate::template::{Field, Fields};
+
+/// A set of bytes, as inclusive ranges.
+#[derive(Clone, Copy, Debug)]
+pub struct Class(pub &'static [(u8, u8)]);
+
+impl Class {
+    pub fn contains(&self, c: u8) -> bool {
+        self.0.iter().any(|&(lo, hi)| lo <= c && c <= hi)
+    }
+}
+
+pub const DIGIT: Class = Class(&[(b'0', b'9')]);
+pub const UPPER: Class = Class(&[(b'A', b'Z')]);
+pub const LOWER: Class = Class(&[(b'a', b'z')]);
+/// Printable ASCII including the space.
+pub const PRINTABLE: Class = Class(&[(b' ', b'~')]);
+const AMOUNT_INT: Class = Class(&[(b'0', b'9'), (b',', b',')]);
+/// SC prints the payee name in capitals, so lowercase text such as the
+/// rest of the sentence cannot follow it.
+const NAME: Class = Class(&[
+    (b'A', b'Z'),
+    (b' ', b' '),
+    (b'\'', b'\''),
+    (b'*', b'*'),
+    (b'-', b'.'),
+]);
+const EMAIL_LOCAL: Class = Class(&[
+    (b'A', b'Z'),
+    (b'a', b'z'),
+    (b'0', b'9'),
+    (b'*', b'+'),
+    (b'-', b'.'),
+    (b'_', b'_'),
+]);
+const EMAIL_DOMAIN: Class = Class(&[(b'A', b'Z'), (b'a', b'z'), (b'0', b'9'), (b'-', b'.')]);
+/// The display name of the `To:` address, without quotes, separators or
+/// angle brackets.
+const DISPLAY_NAME: Class = Class(&[
+    (b'A', b'Z'),
+    (b'a', b'z'),
+    (b'0', b'9'),
+    (b' ', b' '),
+    (b'\'', b'\''),
+    (b'*', b'+'),
+    (b'-', b'.'),
+    (b'@', b'@'),
+    (b'_', b'_'),
+]);
+const SIGN: Class = Class(&[(b'+', b'+'), (b'-', b'-')]);
+const MESSAGE_ID: Class = Class(&[(b'!', b';'), (b'=', b'='), (b'?', b'~')]);
+const BASE64: Class = Class(&[
+    (b'A', b'Z'),
+    (b'a', b'z'),
+    (b'0', b'9'),
+    (b'+', b'+'),
+    (b'/', b'/'),
+]);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Token {
+    Lit(&'static [u8]),
+    /// Between `min` and `max` bytes of the class.
+    Run(Class, usize, usize),
+}
+
+use Token::{Lit, Run};
+
+/// Whether `pattern` matches the whole of `data`.
+pub fn matches(pattern: &[Token], data: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => data.is_empty(),
+        Some((Lit(lit), rest)) => data.starts_with(lit) && matches(rest, &data[lit.len()..]),
+        Some((Run(class, min, max), rest)) => {
+            let run = data
+                .iter()
+                .take(*max)
+                .take_while(|&&c| class.contains(c))
+                .count();
+            run >= *min && (*min..=run).rev().any(|n| matches(rest, &data[n..]))
+        }
+    }
+}
+
+/// A single address, as in `Field::Email`.
+const ADDRESS: &[Token] = &[
+    Run(EMAIL_LOCAL, 1, 64),
+    Lit(b"@"),
+    Run(EMAIL_DOMAIN, 1, 253),
+];
+
+/// The patterns that the value of `field` may match, one of which must.
+pub fn patterns(field: Field) -> &'static [&'static [Token]] {
+    match field {
+        // The MIME boundary grammar of SC, e.g. `2772651_343290592.1700310140503`.
+        Field::CommentLine => &[&[
+            Run(DIGIT, 1, 20),
+            Lit(b"_"),
+            Run(DIGIT, 1, 20),
+            Lit(b"."),
+            Run(DIGIT, 1, 20),
+        ]],
+        Field::Amount => &[&[Run(AMOUNT_INT, 1, 24), Lit(b"."), Run(DIGIT, 2, 2)]],
+        Field::Name => &[&[Run(NAME, 1, 64)]],
+        Field::Email => &[ADDRESS],
+        Field::DateBody => &[&[
+            Run(DIGIT, 2, 2),
+            Lit(b"/"),
+            Run(DIGIT, 2, 2),
+            Lit(b"/"),
+            Run(DIGIT, 4, 4),
+        ]],
+        Field::DateHead => &[&[
+            Run(UPPER, 1, 1),
+            Run(LOWER, 2, 2),
+            Lit(b", "),
+            Run(DIGIT, 1, 2),
+            Lit(b" "),
+            Run(UPPER, 1, 1),
+            Run(LOWER, 2, 2),
+            Lit(b" "),
+            Run(DIGIT, 4, 4),
+            Lit(b" "),
+            Run(DIGIT, 2, 2),
+            Lit(b":"),
+            Run(DIGIT, 2, 2),
+            Lit(b":"),
+            Run(DIGIT, 2, 2),
+            Lit(b" "),
+            Run(SIGN, 1, 1),
+            Run(DIGIT, 4, 4),
+        ]],
+        // One address, either bare or as `"NAME" <address>`.
+        Field::Receiver => &[
+            ADDRESS,
+            &[
+                Lit(b"\""),
+                Run(DISPLAY_NAME, 1, 128),
+                Lit(b"\" <"),
+                Run(EMAIL_LOCAL, 1, 64),
+                Lit(b"@"),
+                Run(EMAIL_DOMAIN, 1, 253),
+                Lit(b">"),
+            ],
+        ],
+        Field::MessageId => &[&[Lit(b"<"), Run(MESSAGE_ID, 1, 250), Lit(b">")]],
+        Field::DkimTimestamp => &[&[Run(DIGIT, 1, 12)]],
+        Field::BhBase64 => &[&[Run(BASE64, 43, 43), Lit(b"=")]],
+        Field::ReceiptNumber => &[&[Run(DIGIT, 4, 4), Lit(b"-"), Run(DIGIT, 12, 12)]],
+    }
+}
+
+/// Whether `value` matches one of the patterns of `field`.
+pub fn accepts(field: Field, value: &[u8]) -> bool {
+    patterns(field)
+        .iter()
+        .any(|pattern| matches(pattern, value))
+}
+
+pub const FIELDS: [Field; 11] = [
+    Field::CommentLine,
+    Field::Amount,
+    Field::Name,
+    Field::Email,
+    Field::DateBody,
+    Field::DateHead,
+    Field::Receiver,
+    Field::MessageId,
+    Field::DkimTimestamp,
+    Field::BhBase64,
+    Field::ReceiptNumber,
+];
+
+/// Check every field against its pattern, and return the first that fails.
+pub fn check(fields: &impl Fields) -> Result<(), Field> {
+    for field in FIELDS {
+        if !accepts(field, fields.field(field)) {
+            return Err(field);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::sample;
+    use alloc::vec::Vec;
+
+    /// Template text that a field could try to smuggle in: a new header
+    /// line, the separators around the name and the email, the rest of the
+    /// sentence, a parameter separator, and a MIME delimiter.
+    const INJECTIONS: &[&[u8]] = &[
+        b"\r\n",
+        b"\r\nBcc: x@example.com",
+        b", ",
+        b" via SC Pay",
+        b"; ",
+        b"------=_Part_2772651_343290592.1700310140503",
+        b"\r\n------=_Part_2772651_343290592.1700310140503--",
+    ];
+
+    #[test]
+    fn sample_passes() {
+        assert_eq!(check(&sample()), Ok(()));
+        for field in FIELDS {
+            assert!(accepts(field, sample().field(field)), "{:?}", field);
+        }
+    }
+
+    #[test]
+    fn every_field_rejects_injected_text() {
+        let mut accepted = Vec::new();
+        for field in FIELDS {
+            let value = sample().field(field).to_vec();
+            for injection in INJECTIONS {
+                let mut appended = value.clone();
+                appended.extend_from_slice(injection);
+                let mut prepended = injection.to_vec();
+                prepended.extend_from_slice(&value);
+
+                for bad in [appended, prepended, injection.to_vec()] {
+                    if accepts(field, &bad) {
+                        accepted.push((field, bad));
+                    }
+                }
+            }
+        }
+        assert!(accepted.is_empty(), "{:?}", accepted);
+    }
+
+    #[test]
+    fn receiver_is_one_address() {
+        assert!(accepts(Field::Receiver, b"user@example.com"));
+        assert!(accepts(Field::Receiver, b"\"Chen W.\" <user@example.com>"));
+        assert!(!accepts(Field::Receiver, b"a@example.com, b@example.com"));
+        assert!(!accepts(
+            Field::Receiver,
+            b"\"a@example.com\" <a@example.com>, b@example.com"
+        ));
+        assert!(!accepts(Field::Receiver, b"\"A, B\" <a@example.com>"));
+        assert!(!accepts(Field::Receiver, b"\"A\\\" <b@example.com>"));
+    }
+
+    #[test]
+    fn comment_line_cannot_leave_the_boundary() {
+        for bad in [
+            &b"2772651_343290592.1700310140503\""[..],
+            b"2772651_343290592.1700310140503\"; x=\"",
+            b"2772651_343290592.1700310140503--",
+            b"--2772651_343290592.1700310140503",
+            b"2772651--343290592.1700310140503",
+            b"2772651_343290592.1700310140503\r\n--",
+        ] {
+            assert!(!accepts(Field::CommentLine, bad), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn check_reports_the_failing_field() {
+        let mut witness = sample();
+        witness.receipt_number.extend_from_slice(b"\r\n");
+        assert_eq!(check(&witness), Err(Field::ReceiptNumber));
+
+        let mut witness = sample();
+        witness.name = Vec::new();
+        assert_eq!(check(&witness), Err(Field::Name));
+    }
+}